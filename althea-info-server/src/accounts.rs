@@ -0,0 +1,166 @@
+//! Account growth and activity metrics. Account counts come from the supply crawl, which already lists every
+//! account on chain, while daily active accounts come from the transactions seen by the tx indexer
+
+use crate::{
+    history::{
+        day_start, get_current_timestamp, load_last_snapshot, save_snapshot, Snapshot,
+        SECONDS_PER_DAY,
+    },
+    tx_indexer::get_tx_index,
+};
+use deep_space::client::types::AccountType;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+
+/// The name of the history file account stats snapshots are stored in
+pub const ACCOUNT_STATS_HISTORY: &str = "account_stats";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountCounts {
+    pub total_accounts: u64,
+    /// One of base, module, continuous_vesting, periodic_vesting, delayed_vesting or permanent_locked -> count
+    pub accounts_by_type: BTreeMap<String, u64>,
+    /// Account numbers are assigned sequentially as accounts are created, so this is the total number of
+    /// accounts ever created
+    pub latest_account_number: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountStats {
+    #[serde(flatten)]
+    pub counts: AccountCounts,
+    /// Accounts created since the previous snapshot, null for the first snapshot
+    pub new_accounts: Option<u64>,
+    /// Accounts which signed at least one transaction on the previous UTC day, null if the tx index has no
+    /// transactions for that day
+    pub daily_active_accounts: Option<u64>,
+}
+
+/// Addresses which signed transactions, only the current day's addresses are kept, earlier days are reduced
+/// to a count once the indexer moves past them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ActiveAccounts {
+    /// Start of the day `today` holds addresses for
+    day: u64,
+    today: BTreeSet<String>,
+    /// Start of day unix timestamp (UTC) -> number of active accounts
+    daily: BTreeMap<u64, u64>,
+}
+
+impl ActiveAccounts {
+    /// Records an address as active on `day`, days must be recorded in order
+    pub fn record(&mut self, day: u64, address: String) {
+        if day > self.day {
+            if !self.today.is_empty() {
+                self.daily.insert(self.day, self.today.len() as u64);
+            }
+            self.today.clear();
+            self.day = day;
+        }
+        if day == self.day {
+            self.today.insert(address);
+        }
+    }
+
+    /// Returns the number of active accounts on the given day, None if no transactions were indexed for it
+    pub fn get(&self, day: u64) -> Option<u64> {
+        if day == self.day && !self.today.is_empty() {
+            Some(self.today.len() as u64)
+        } else {
+            self.daily.get(&day).copied()
+        }
+    }
+}
+
+lazy_static! {
+    static ref ACCOUNT_STATS: Arc<RwLock<Option<AccountStats>>> = Arc::new(RwLock::new(None));
+}
+
+fn set_account_stats(input: AccountStats) {
+    let mut r = ACCOUNT_STATS.write().unwrap();
+    *r = Some(input);
+}
+
+pub fn get_account_stats() -> Option<AccountStats> {
+    ACCOUNT_STATS.read().unwrap().clone()
+}
+
+pub fn account_type_name(account: &AccountType) -> &'static str {
+    match account {
+        AccountType::ProtoBaseAccount(_) => "base",
+        AccountType::ModuleAccount(_) => "module",
+        AccountType::ContinuousVestingAccount(_) => "continuous_vesting",
+        AccountType::PeriodicVestingAccount(_) => "periodic_vesting",
+        AccountType::DelayedVestingAccount(_) => "delayed_vesting",
+        AccountType::PermenantLockedAccount(_) => "permanent_locked",
+    }
+}
+
+pub fn count_accounts<'a>(accounts: impl IntoIterator<Item = &'a AccountType>) -> AccountCounts {
+    let mut counts = AccountCounts::default();
+    for account in accounts {
+        *counts
+            .accounts_by_type
+            .entry(account_type_name(account).to_string())
+            .or_default() += 1;
+        counts.total_accounts += 1;
+        counts.latest_account_number = counts
+            .latest_account_number
+            .max(account.get_base_account().account_number);
+    }
+    counts
+}
+
+/// Combines the account counts from a supply crawl with the previous snapshot and the tx index, then stores
+/// the result as a new snapshot
+pub fn update_account_stats(counts: AccountCounts) {
+    let previous: Option<Snapshot<AccountStats>> = load_last_snapshot(ACCOUNT_STATS_HISTORY);
+    let timestamp = get_current_timestamp();
+    let yesterday = day_start(timestamp) - SECONDS_PER_DAY;
+
+    let stats = AccountStats {
+        new_accounts: previous.map(|p| {
+            counts
+                .latest_account_number
+                .saturating_sub(p.data.counts.latest_account_number)
+        }),
+        daily_active_accounts: get_tx_index().and_then(|i| i.active_accounts.get(yesterday)),
+        counts,
+    };
+    if let Err(e) = save_snapshot(
+        ACCOUNT_STATS_HISTORY,
+        &Snapshot {
+            timestamp,
+            data: stats.clone(),
+        },
+    ) {
+        error!("Failed to save account stats snapshot with {:?}", e);
+    }
+    set_account_stats(stats);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_active_accounts() {
+        let mut active = ActiveAccounts::default();
+        active.record(SECONDS_PER_DAY, "a".to_string());
+        active.record(SECONDS_PER_DAY, "a".to_string());
+        active.record(SECONDS_PER_DAY, "b".to_string());
+        assert_eq!(active.get(SECONDS_PER_DAY), Some(2));
+
+        active.record(SECONDS_PER_DAY * 3, "a".to_string());
+        assert_eq!(active.get(SECONDS_PER_DAY), Some(2));
+        assert_eq!(active.get(SECONDS_PER_DAY * 2), None);
+        assert_eq!(active.get(SECONDS_PER_DAY * 3), Some(1));
+
+        // days before the current one are already finalized
+        active.record(SECONDS_PER_DAY, "c".to_string());
+        assert_eq!(active.get(SECONDS_PER_DAY), Some(2));
+        assert_eq!(active.get(SECONDS_PER_DAY * 3), Some(1));
+    }
+}
@@ -0,0 +1,155 @@
+//! Reads block events from the Tendermint RPC block_results endpoint. Unlike transaction queries these include
+//! events emitted outside of transactions, such as completed unbondings, so they can be used to find every
+//! account whose balances changed in a block
+
+use crate::REQUEST_TIMEOUT;
+use deep_space::Address;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use web30::jsonrpc::client::HttpClient;
+
+/// Bank, staking and distribution events which change the balance, delegations, unbonding delegations or
+/// unclaimed rewards of the accounts they reference
+const BALANCE_EVENT_TYPES: [&str; 12] = [
+    "transfer",
+    "coin_spent",
+    "coin_received",
+    "coinbase",
+    "burn",
+    "delegate",
+    "unbond",
+    "redelegate",
+    "complete_unbonding",
+    "cancel_unbonding_delegation",
+    "withdraw_rewards",
+    "withdraw_commission",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockResults {
+    #[serde(default)]
+    pub txs_results: Option<Vec<TxResult>>,
+    /// Replaced by finalize_block_events in CometBFT 0.38
+    #[serde(default)]
+    pub begin_block_events: Option<Vec<Event>>,
+    /// Replaced by finalize_block_events in CometBFT 0.38
+    #[serde(default)]
+    pub end_block_events: Option<Vec<Event>>,
+    #[serde(default)]
+    pub finalize_block_events: Option<Vec<Event>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TxResult {
+    #[serde(default)]
+    pub events: Option<Vec<Event>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Event {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub attributes: Vec<EventAttribute>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventAttribute {
+    pub key: String,
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+pub async fn get_block_results(rpc: &HttpClient, height: u64) -> Result<BlockResults, String> {
+    #[derive(Debug, serde::Serialize)]
+    struct Params {
+        height: String,
+    }
+    rpc.request_method(
+        "block_results",
+        Params {
+            height: height.to_string(),
+        },
+        REQUEST_TIMEOUT,
+    )
+    .await
+    .map_err(|e| format!("Failed to get block results for {height}: {e}"))
+}
+
+/// Returns every account with the given prefix referenced by a bank, staking or distribution event in the block
+pub fn get_touched_accounts(results: &BlockResults, prefix: &str) -> BTreeSet<String> {
+    let tx_events = results
+        .txs_results
+        .iter()
+        .flatten()
+        .flat_map(|tx| tx.events.iter().flatten());
+    let block_events = [
+        &results.begin_block_events,
+        &results.end_block_events,
+        &results.finalize_block_events,
+    ]
+    .into_iter()
+    .flat_map(|events| events.iter().flatten());
+
+    let account_prefix = format!("{prefix}1");
+    let mut accounts = BTreeSet::new();
+    for event in tx_events.chain(block_events) {
+        if !BALANCE_EVENT_TYPES.contains(&event.kind.as_str()) {
+            continue;
+        }
+        for value in event.attributes.iter().filter_map(|a| a.value.as_ref()) {
+            // validator operator addresses use a different prefix and are skipped here
+            if value.starts_with(&account_prefix) && Address::from_bech32(value.clone()).is_ok() {
+                accounts.insert(value.clone());
+            }
+        }
+    }
+    accounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_touched_accounts() {
+        let sender = Address::from_slice(&[1u8; 20], "althea")
+            .unwrap()
+            .to_bech32("althea")
+            .unwrap();
+        let delegator = Address::from_slice(&[2u8; 20], "althea")
+            .unwrap()
+            .to_bech32("althea")
+            .unwrap();
+        let validator = Address::from_slice(&[3u8; 20], "altheavaloper")
+            .unwrap()
+            .to_bech32("altheavaloper")
+            .unwrap();
+        let results: BlockResults = serde_json::from_value(serde_json::json!({
+            "height": "100",
+            "txs_results": [{
+                "code": 0,
+                "events": [
+                    {"type": "coin_spent", "attributes": [
+                        {"key": "spender", "value": sender, "index": true},
+                        {"key": "amount", "value": "100aalthea", "index": true}
+                    ]},
+                    {"type": "message", "attributes": [
+                        {"key": "sender", "value": "althea1notanaddress", "index": true}
+                    ]}
+                ]
+            }],
+            "finalize_block_events": [
+                {"type": "complete_unbonding", "attributes": [
+                    {"key": "validator", "value": validator, "index": true},
+                    {"key": "delegator", "value": delegator, "index": true}
+                ]}
+            ],
+            "validator_updates": null
+        }))
+        .unwrap();
+
+        let touched = get_touched_accounts(&results, "althea");
+        assert_eq!(touched, [sender, delegator].into_iter().collect());
+    }
+}
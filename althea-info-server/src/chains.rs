@@ -0,0 +1,228 @@
+//! The Cosmos chains the server computes supply info for. Althea L1 is always served, other chains can be added
+//! with a JSON config file listing them. Everything besides supply info and a basic TVL is Althea specific and is
+//! only computed for Althea L1
+
+use crate::{
+    config::get_tokens, settings::get_settings, total_suppy::ALTHEA_DENOM, ALTHEA_NODE_GRPC,
+    ALTHEA_PREFIX, ALTHEA_TENDERMINT_RPC,
+};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use tonic::transport::Uri;
+
+pub const ALTHEA_CHAIN_ID: &str = "althea_258432-1";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainConfig {
+    pub chain_id: String,
+    pub name: String,
+    /// The first is used for single queries, the account crawl spreads its connections across all of them
+    #[serde(skip_serializing)]
+    pub grpc_urls: Vec<String>,
+    /// The Tendermint RPC used to update supply info from new blocks between full crawls, if not set supply info
+    /// is only updated by full crawls
+    #[serde(default, skip_serializing)]
+    pub tendermint_rpc: Option<String>,
+    /// The bech32 prefix of account addresses
+    pub prefix: String,
+    pub native_denom: String,
+    /// Tokens reported in module account balances and the TVL
+    #[serde(default)]
+    pub tokens: Vec<ChainToken>,
+}
+
+impl ChainConfig {
+    pub fn grpc(&self) -> &str {
+        &self.grpc_urls[0]
+    }
+
+    pub fn get_token(&self, denom: &str) -> Option<&ChainToken> {
+        self.tokens.iter().find(|t| t.denom == denom)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChainToken {
+    pub denom: String,
+    pub symbol: String,
+    pub decimals: u32,
+    #[serde(default)]
+    pub coingecko_id: Option<String>,
+}
+
+lazy_static! {
+    static ref CHAINS: Result<Vec<ChainConfig>, String> = load_chains();
+}
+
+/// Loads the chains config, called on startup so that the server refuses to start with an invalid config
+pub fn check_chains() -> Result<(), String> {
+    CHAINS.as_ref().map(|_| ()).map_err(|e| e.clone())
+}
+
+/// Every configured chain, Althea L1 first
+pub fn get_chains() -> &'static [ChainConfig] {
+    CHAINS
+        .as_deref()
+        .expect("The chains config is checked on startup")
+}
+
+pub fn get_chain(chain_id: &str) -> Option<&'static ChainConfig> {
+    get_chains().iter().find(|c| c.chain_id == chain_id)
+}
+
+pub fn is_althea(chain: &ChainConfig) -> bool {
+    chain.chain_id == ALTHEA_CHAIN_ID
+}
+
+fn althea_chain() -> ChainConfig {
+    let mut tokens: Vec<ChainToken> = get_tokens()
+        .into_values()
+        .filter_map(|t| {
+            Some(ChainToken {
+                denom: t.althea_denom?,
+                symbol: t.symbol,
+                decimals: t.decimals,
+                coingecko_id: Some(t.coingecko_id),
+            })
+        })
+        .collect();
+    tokens.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    ChainConfig {
+        chain_id: ALTHEA_CHAIN_ID.to_string(),
+        name: "Althea L1".to_string(),
+        grpc_urls: vec![ALTHEA_NODE_GRPC.to_string()],
+        tendermint_rpc: Some(ALTHEA_TENDERMINT_RPC.to_string()),
+        prefix: ALTHEA_PREFIX.to_string(),
+        native_denom: ALTHEA_DENOM.to_string(),
+        tokens,
+    }
+}
+
+/// Loads Althea L1 and the chains in the chains config file, if any
+fn load_chains() -> Result<Vec<ChainConfig>, String> {
+    let mut chains = vec![althea_chain()];
+    let path = &get_settings().chains_config;
+    if path.is_empty() {
+        return Ok(chains);
+    }
+    let extra = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|c| parse_chains(&c))
+        .map_err(|e| format!("Invalid chains config {path} with {e}"))?;
+    info!("Serving {} chains from {}", extra.len(), path);
+    chains.extend(extra);
+    Ok(chains)
+}
+
+fn parse_chains(input: &str) -> Result<Vec<ChainConfig>, String> {
+    let chains: Vec<ChainConfig> = serde_json::from_str(input).map_err(|e| e.to_string())?;
+    let mut ids = vec![ALTHEA_CHAIN_ID.to_string()];
+    for chain in chains.iter() {
+        check_chain(chain)?;
+        if ids.contains(&chain.chain_id) {
+            return Err(format!("{} is listed more than once", chain.chain_id));
+        }
+        ids.push(chain.chain_id.clone());
+    }
+    Ok(chains)
+}
+
+/// Checks the fields serde can't, so that the workers never find a chain they can't query
+fn check_chain(chain: &ChainConfig) -> Result<(), String> {
+    let id = &chain.chain_id;
+    for (field, value) in [
+        ("chain_id", id),
+        ("name", &chain.name),
+        ("prefix", &chain.prefix),
+        ("native_denom", &chain.native_denom),
+    ] {
+        if value.is_empty() {
+            return Err(format!("A chain has an empty {field}, chain id {id:?}"));
+        }
+    }
+    if chain.grpc_urls.is_empty() {
+        return Err(format!("{id} has no gRPC urls"));
+    }
+    for url in chain.grpc_urls.iter().chain(chain.tendermint_rpc.iter()) {
+        check_url(url).map_err(|e| format!("{id} has an invalid url {url:?}, {e}"))?;
+    }
+    let mut denoms = Vec::new();
+    for token in chain.tokens.iter() {
+        if token.denom.is_empty() {
+            return Err(format!("{id} has a token with an empty denom"));
+        }
+        if denoms.contains(&&token.denom) {
+            return Err(format!(
+                "{id} lists the token {} more than once",
+                token.denom
+            ));
+        }
+        denoms.push(&token.denom);
+    }
+    Ok(())
+}
+
+/// Urls must be absolute http or https urls
+fn check_url(url: &str) -> Result<(), String> {
+    let uri: Uri = url.parse().map_err(|e| format!("{e}"))?;
+    match uri.scheme_str() {
+        Some("http") | Some("https") if uri.host().is_some() => Ok(()),
+        _ => Err("expected an http or https url".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chains() {
+        let chains = parse_chains(
+            r#"[{
+                "chain_id": "cosmoshub-4",
+                "name": "Cosmos Hub",
+                "grpc_urls": ["http://localhost:9090", "http://localhost:9091"],
+                "prefix": "cosmos",
+                "native_denom": "uatom",
+                "tokens": [{"denom": "uatom", "symbol": "ATOM", "decimals": 6}]
+            }]"#,
+        )
+        .unwrap();
+        assert_eq!(chains[0].grpc(), "http://localhost:9090");
+        assert_eq!(chains[0].tendermint_rpc, None);
+        assert_eq!(chains[0].get_token("uatom").unwrap().symbol, "ATOM");
+
+        assert!(parse_chains(
+            r#"[{"chain_id": "a", "name": "a", "grpc_urls": [], "prefix": "a", "native_denom": "a"}]"#
+        )
+        .is_err());
+        // missing and empty fields
+        assert!(
+            parse_chains(r#"[{"chain_id": "a", "name": "a", "grpc_urls": ["http://a"]}]"#).is_err()
+        );
+        assert!(parse_chains(
+            r#"[{"chain_id": "a", "name": "a", "grpc_urls": ["http://a"], "prefix": "", "native_denom": "a"}]"#
+        )
+        .is_err());
+        // invalid urls
+        assert!(parse_chains(
+            r#"[{"chain_id": "a", "name": "a", "grpc_urls": ["localhost:9090"], "prefix": "a", "native_denom": "a"}]"#
+        )
+        .is_err());
+        assert!(parse_chains(
+            r#"[{"chain_id": "a", "name": "a", "grpc_urls": ["http://a"], "tendermint_rpc": "", "prefix": "a", "native_denom": "a"}]"#
+        )
+        .is_err());
+        // a token listed twice
+        assert!(parse_chains(
+            r#"[{"chain_id": "a", "name": "a", "grpc_urls": ["http://a"], "prefix": "a", "native_denom": "a",
+                "tokens": [{"denom": "a", "symbol": "A", "decimals": 6}, {"denom": "a", "symbol": "B", "decimals": 6}]}]"#
+        )
+        .is_err());
+        assert!(parse_chains(&format!(
+            r#"[{{"chain_id": "{ALTHEA_CHAIN_ID}", "name": "a", "grpc_urls": ["a"], "prefix": "a", "native_denom": "a"}}]"#
+        ))
+        .is_err());
+    }
+}
@@ -0,0 +1,343 @@
+//! Community pool inflows and outflows. Outflows are passed community pool spend proposals, either legacy
+//! CommunityPoolSpendProposals or gov v1 proposals executing MsgCommunityPoolSpend. Inflows are not
+//! recorded on chain so they are computed from the change in the pool balance between snapshots plus whatever
+//! was spent in between
+
+use crate::{
+    gov_v1::MsgCommunityPoolSpend,
+    governance::{GovClient, GovProposal},
+    history::{get_current_timestamp, load_last_snapshot, save_snapshot, Snapshot},
+    inflation::dec_to_uint,
+    total_suppy::ALTHEA_DENOM,
+    ALTHEA_NODE_GRPC,
+};
+use cosmos_sdk_proto_althea::cosmos::base::v1beta1::Coin as ProtoCoin;
+use cosmos_sdk_proto_althea::cosmos::distribution::v1beta1::query_client::QueryClient as DistQueryClient;
+use cosmos_sdk_proto_althea::cosmos::distribution::v1beta1::{
+    CommunityPoolSpendProposal, QueryCommunityPoolRequest,
+};
+use cosmos_sdk_proto_althea::cosmos::gov::v1beta1::ProposalStatus;
+use deep_space::error::CosmosGrpcError;
+use log::{error, info};
+use num256::Uint256;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::sleep;
+
+// update once an hour, each update is stored as a snapshot
+const LOOP_TIME: Duration = Duration::from_secs(3600);
+/// The name of the history file community pool snapshots are stored in
+pub const COMMUNITY_POOL_HISTORY: &str = "community_pool";
+const COMMUNITY_POOL_SPEND_TYPE_URL: &str =
+    "/cosmos.distribution.v1beta1.CommunityPoolSpendProposal";
+const MSG_COMMUNITY_POOL_SPEND_TYPE_URL: &str =
+    "/cosmos.distribution.v1beta1.MsgCommunityPoolSpend";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommunityPoolSpend {
+    pub proposal_id: u64,
+    pub title: String,
+    pub recipient: String,
+    /// Denom -> amount sent to the recipient
+    pub amount: BTreeMap<String, Uint256>,
+    /// Unix timestamp in seconds of when the proposal passed and the funds were sent
+    pub executed_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommunityPoolInfo {
+    /// The current community pool balance in the native denom
+    pub balance: Uint256,
+    /// The total amount of the native denom ever spent from the community pool
+    pub total_spent: Uint256,
+    /// Every spend of a passed proposal, newest first, a v1 proposal may make several
+    pub spends: Vec<CommunityPoolSpend>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommunityPoolFlows {
+    /// The community pool balance in the native denom when this snapshot was taken
+    pub balance: Uint256,
+    /// Native tokens added to the pool since the previous snapshot, this is mostly the community tax but
+    /// includes direct deposits. Null for the first snapshot
+    pub inflows: Option<Uint256>,
+    /// Native tokens spent from the pool since the previous snapshot
+    pub outflows: Uint256,
+    /// The spend proposals executed since the previous snapshot
+    pub spends: Vec<CommunityPoolSpend>,
+}
+
+lazy_static! {
+    static ref COMMUNITY_POOL_INFO: Arc<RwLock<Option<CommunityPoolInfo>>> =
+        Arc::new(RwLock::new(None));
+}
+
+fn set_community_pool_info(input: CommunityPoolInfo) {
+    let mut r = COMMUNITY_POOL_INFO.write().unwrap();
+    *r = Some(input);
+}
+
+pub fn get_community_pool_info() -> Option<CommunityPoolInfo> {
+    COMMUNITY_POOL_INFO.read().unwrap().clone()
+}
+
+pub async fn community_pool_worker() {
+    loop {
+        match compute_community_pool_info(ALTHEA_NODE_GRPC).await {
+            Ok(v) => {
+                info!("Successfully updated community pool info!");
+                let previous: Option<Snapshot<CommunityPoolFlows>> =
+                    load_last_snapshot(COMMUNITY_POOL_HISTORY);
+                let timestamp = get_current_timestamp();
+                let flows = compute_flows(&v, previous.as_ref(), timestamp);
+                if let Err(e) = save_snapshot(
+                    COMMUNITY_POOL_HISTORY,
+                    &Snapshot {
+                        timestamp,
+                        data: flows,
+                    },
+                ) {
+                    error!("Failed to save community pool snapshot with {:?}", e);
+                }
+                set_community_pool_info(v);
+            }
+            Err(e) => error!("Failed to update community pool info with {:?}", e),
+        }
+        sleep(LOOP_TIME).await;
+    }
+}
+
+pub async fn compute_community_pool_info(grpc: &str) -> Result<CommunityPoolInfo, CosmosGrpcError> {
+    let mut distrpc = DistQueryClient::connect(grpc.to_string()).await?;
+
+    let mut balance: Uint256 = 0u8.into();
+    for coin in distrpc
+        .community_pool(QueryCommunityPoolRequest {})
+        .await?
+        .into_inner()
+        .pool
+    {
+        if coin.denom == ALTHEA_DENOM {
+            balance = dec_to_uint(&coin.amount).ok_or_else(|| {
+                CosmosGrpcError::BadResponse(format!("Invalid community pool {}", coin.amount))
+            })?;
+        }
+    }
+
+    let (_, proposals) = GovClient::connect(grpc, ProposalStatus::Passed).await?;
+    let mut spends = Vec::new();
+    for proposal in proposals {
+        spends.extend(proposal_spends(&proposal).map_err(CosmosGrpcError::BadResponse)?);
+    }
+    spends.sort_by_key(|s| std::cmp::Reverse(s.proposal_id));
+
+    let total_spent = spends
+        .iter()
+        .fold(0u8.into(), |acc: Uint256, s| acc + native_amount(s));
+    Ok(CommunityPoolInfo {
+        balance,
+        total_spent,
+        spends,
+    })
+}
+
+/// The community pool spends a passed proposal executed, empty if it isn't a spend proposal
+fn proposal_spends(proposal: &GovProposal) -> Result<Vec<CommunityPoolSpend>, String> {
+    let mut spends = Vec::new();
+    for message in &proposal.messages {
+        let (title, recipient, coins) = match message.type_url.as_str() {
+            COMMUNITY_POOL_SPEND_TYPE_URL => {
+                let spend = CommunityPoolSpendProposal::decode(message.value.as_slice())
+                    .map_err(|e| format!("Invalid spend proposal {} {e}", proposal.id))?;
+                (spend.title, spend.recipient, spend.amount)
+            }
+            MSG_COMMUNITY_POOL_SPEND_TYPE_URL => {
+                let spend =
+                    MsgCommunityPoolSpend::decode(message.value.as_slice()).map_err(|e| {
+                        format!("Invalid spend message in proposal {} {e}", proposal.id)
+                    })?;
+                (proposal.title.clone(), spend.recipient, spend.amount)
+            }
+            _ => continue,
+        };
+        // funds are sent when the voting period ends, so a passed proposal without an end time can't be placed
+        // between snapshots
+        let executed_at = proposal
+            .voting_end_time
+            .as_ref()
+            .map(|t| t.seconds)
+            .ok_or_else(|| format!("Passed proposal {} has no voting end time", proposal.id))?;
+        spends.push(CommunityPoolSpend {
+            proposal_id: proposal.id,
+            title,
+            recipient,
+            amount: sum_coins(proposal.id, coins)?,
+            executed_at,
+        });
+    }
+    Ok(spends)
+}
+
+fn sum_coins(proposal_id: u64, coins: Vec<ProtoCoin>) -> Result<BTreeMap<String, Uint256>, String> {
+    let mut amount = BTreeMap::new();
+    for coin in coins {
+        let value: Uint256 = coin.amount.parse().map_err(|_| {
+            format!(
+                "Invalid spend amount {} in proposal {}",
+                coin.amount, proposal_id
+            )
+        })?;
+        *amount.entry(coin.denom).or_insert(0u8.into()) += value;
+    }
+    Ok(amount)
+}
+
+fn native_amount(spend: &CommunityPoolSpend) -> Uint256 {
+    spend
+        .amount
+        .get(ALTHEA_DENOM)
+        .copied()
+        .unwrap_or(0u8.into())
+}
+
+/// Computes the flows since the previous snapshot, every spend executed after the previous snapshot was taken
+/// came out of the pool in between, so inflows = balance - previous balance + outflows
+pub fn compute_flows(
+    info: &CommunityPoolInfo,
+    previous: Option<&Snapshot<CommunityPoolFlows>>,
+    timestamp: u64,
+) -> CommunityPoolFlows {
+    let since = previous.map(|p| p.timestamp as i64).unwrap_or(i64::MIN);
+    let spends: Vec<CommunityPoolSpend> = info
+        .spends
+        .iter()
+        .filter(|s| s.executed_at > since && s.executed_at <= timestamp as i64)
+        .cloned()
+        .collect();
+    let outflows = spends
+        .iter()
+        .fold(0u8.into(), |acc: Uint256, s| acc + native_amount(s));
+    let inflows = previous.map(|p| {
+        let before = p.data.balance;
+        let after = info.balance + outflows;
+        if after > before {
+            after - before
+        } else {
+            0u8.into()
+        }
+    });
+    CommunityPoolFlows {
+        balance: info.balance,
+        inflows,
+        outflows,
+        spends,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_types::{Any, Timestamp};
+
+    #[test]
+    fn test_proposal_spends() {
+        let coin = |amount: &str| ProtoCoin {
+            denom: ALTHEA_DENOM.to_string(),
+            amount: amount.to_string(),
+        };
+        let legacy = CommunityPoolSpendProposal {
+            title: "Legacy".to_string(),
+            description: String::new(),
+            recipient: "a".to_string(),
+            amount: vec![coin("10"), coin("5")],
+        };
+        let msg = |amount: &str| MsgCommunityPoolSpend {
+            authority: String::new(),
+            recipient: "b".to_string(),
+            amount: vec![coin(amount)],
+        };
+        let mut proposal = GovProposal {
+            id: 3,
+            title: "V1".to_string(),
+            status: ProposalStatus::Passed as i32,
+            messages: vec![
+                Any {
+                    type_url: COMMUNITY_POOL_SPEND_TYPE_URL.to_string(),
+                    value: legacy.encode_to_vec(),
+                },
+                Any {
+                    type_url: "/cosmos.bank.v1beta1.MsgSend".to_string(),
+                    value: Vec::new(),
+                },
+                Any {
+                    type_url: MSG_COMMUNITY_POOL_SPEND_TYPE_URL.to_string(),
+                    value: msg("20").encode_to_vec(),
+                },
+            ],
+            final_tally: None,
+            total_deposit: Vec::new(),
+            voting_end_time: Some(Timestamp {
+                seconds: 100,
+                nanos: 0,
+            }),
+        };
+
+        let spends = proposal_spends(&proposal).unwrap();
+        assert_eq!(spends.len(), 2);
+        assert_eq!(spends[0].title, "Legacy");
+        assert_eq!(native_amount(&spends[0]), 15u32.into());
+        assert_eq!(spends[1].title, "V1");
+        assert_eq!(spends[1].recipient, "b");
+        assert_eq!(native_amount(&spends[1]), 20u32.into());
+        assert!(spends.iter().all(|s| s.executed_at == 100));
+
+        // malformed amounts and missing end times are errors rather than zeros
+        proposal.voting_end_time = None;
+        assert!(proposal_spends(&proposal).is_err());
+        proposal.voting_end_time = Some(Timestamp::default());
+        proposal.messages[2].value = msg("1.5").encode_to_vec();
+        assert_eq!(
+            proposal_spends(&proposal).unwrap_err(),
+            "Invalid spend amount 1.5 in proposal 3"
+        );
+    }
+
+    #[test]
+    fn test_compute_flows() {
+        let spend = |id: u64, amount: u32, executed_at: i64| CommunityPoolSpend {
+            proposal_id: id,
+            title: String::new(),
+            recipient: String::new(),
+            amount: [(ALTHEA_DENOM.to_string(), amount.into())].into(),
+            executed_at,
+        };
+        let info = CommunityPoolInfo {
+            balance: 1000u32.into(),
+            total_spent: 500u32.into(),
+            spends: vec![spend(2, 300, 150), spend(1, 200, 50)],
+        };
+
+        let first = compute_flows(&info, None, 200);
+        assert_eq!(first.inflows, None);
+        assert_eq!(first.outflows, 500u32.into());
+
+        let previous = Snapshot {
+            timestamp: 100,
+            data: CommunityPoolFlows {
+                balance: 1200u32.into(),
+                inflows: None,
+                outflows: 0u8.into(),
+                spends: Vec::new(),
+            },
+        };
+        // only the spend after the previous snapshot counts, 1200 - 300 + 100 = 1000
+        let flows = compute_flows(&info, Some(&previous), 200);
+        assert_eq!(flows.spends.len(), 1);
+        assert_eq!(flows.spends[0].proposal_id, 2);
+        assert_eq!(flows.outflows, 300u32.into());
+        assert_eq!(flows.inflows, Some(100u32.into()));
+    }
+}
@@ -8,8 +8,9 @@ use serde::{Deserialize, Serialize};
 pub struct Token {
     /// The Althea L1 EVM address of the token
     pub althea_evm_address: Address,
-    /// The denom of this token on Althea L1 on the Cosmos layer (IBC tokens will have ibc/ prefix)
-    pub althea_denom: String,
+    /// The denom of this token on Althea L1 on the Cosmos layer (IBC tokens will have ibc/ prefix), may be null
+    /// if this token only exists in the Althea L1 EVM, in which case its supply is read from the ERC20 contract
+    pub althea_denom: Option<String>,
     /// The erc20 address of this token on Ethereum, may be null if this token does not exist there
     pub eth_address: Option<Address>,
     /// the source ibc channel of this token, may be null if this token is not an IBC token
@@ -31,7 +32,7 @@ pub fn get_token(token: &str) -> Option<Token> {
         .values()
         .find(|t| {
             t.althea_evm_address.to_string() == token
-                || t.althea_denom == token
+                || t.althea_denom.as_deref() == Some(token)
                 || {
                     if let Some(eth_address) = &t.eth_address {
                         eth_address.to_string() == token
@@ -51,7 +52,7 @@ pub fn get_tokens() -> HashMap<String, Token> {
         althea_evm_address: "0x0000000000000000000000000000000000000000"
             .parse()
             .unwrap(),
-        althea_denom: "aalthea".to_string(),
+        althea_denom: Some("aalthea".to_string()),
         ibc_channel: Some("channel-0".to_string()),
         eth_address: Some(
             "0xF9e595BC0aF20cfa1561dfE085E3DE9Fcf9Fbfa2"
@@ -74,8 +75,9 @@ pub fn get_tokens() -> HashMap<String, Token> {
                 .parse()
                 .unwrap(),
         ),
-        althea_denom: "ibc/17CD484EE7D9723B847D95015FA3EBD1572FD13BC84FB838F55B18A57450F25B"
-            .to_string(),
+        althea_denom: Some(
+            "ibc/17CD484EE7D9723B847D95015FA3EBD1572FD13BC84FB838F55B18A57450F25B".to_string(),
+        ),
         ibc_channel: Some("channel-0".to_string()),
         decimals: 6,
         name: "Circle USD Stablecoin".to_string(),
@@ -93,8 +95,9 @@ pub fn get_tokens() -> HashMap<String, Token> {
                 .parse()
                 .unwrap(),
         ),
-        althea_denom: "ibc/4F6A2DEFEA52CD8D90966ADCB2BD0593D3993AB0DF7F6AEB3EFD6167D79237B0"
-            .to_string(),
+        althea_denom: Some(
+            "ibc/4F6A2DEFEA52CD8D90966ADCB2BD0593D3993AB0DF7F6AEB3EFD6167D79237B0".to_string(),
+        ),
         ibc_channel: Some("channel-0".to_string()),
         decimals: 6,
         name: "Tether Stablecoin".to_string(),
@@ -107,8 +110,9 @@ pub fn get_tokens() -> HashMap<String, Token> {
         althea_evm_address: "0xd567B3d7B8FE3C79a1AD8dA978812cfC4Fa05e75"
             .parse()
             .unwrap(),
-        althea_denom: "ibc/AE1B617F7F329ED83C20AC584B03579EEFE3322EF601CE88936A0271BE1157DD"
-            .to_string(),
+        althea_denom: Some(
+            "ibc/AE1B617F7F329ED83C20AC584B03579EEFE3322EF601CE88936A0271BE1157DD".to_string(),
+        ),
         eth_address: Some(
             "0xdC035D45d973E3EC169d2276DDab16f1e407384F"
                 .parse()
@@ -131,8 +135,9 @@ pub fn get_tokens() -> HashMap<String, Token> {
                 .parse()
                 .unwrap(),
         ),
-        althea_denom: "ibc/576150049104D47DFD447482EEED2FC8B44AB0D9A772D673717062B49D9820C5"
-            .to_string(),
+        althea_denom: Some(
+            "ibc/576150049104D47DFD447482EEED2FC8B44AB0D9A772D673717062B49D9820C5".to_string(),
+        ),
         ibc_channel: Some("channel-0".to_string()),
         decimals: 18,
         name: "Savings USDS".to_string(),
@@ -150,8 +155,9 @@ pub fn get_tokens() -> HashMap<String, Token> {
                 .parse()
                 .unwrap(),
         ),
-        althea_denom: "ibc/FC9D92EC12BC974E8B6179D411351524CD5C2EBC3CE29D5BA856414FEFA47093"
-            .to_string(),
+        althea_denom: Some(
+            "ibc/FC9D92EC12BC974E8B6179D411351524CD5C2EBC3CE29D5BA856414FEFA47093".to_string(),
+        ),
         ibc_channel: Some("channel-0".to_string()),
         decimals: 6,
         name: "Graviton".to_string(),
@@ -169,8 +175,9 @@ pub fn get_tokens() -> HashMap<String, Token> {
                 .parse()
                 .unwrap(),
         ),
-        althea_denom: "ibc/DC186CA7A8C009B43774EBDC825C935CABA9743504CE6037507E6E5CCE12858A"
-            .to_string(),
+        althea_denom: Some(
+            "ibc/DC186CA7A8C009B43774EBDC825C935CABA9743504CE6037507E6E5CCE12858A".to_string(),
+        ),
         ibc_channel: Some("channel-0".to_string()),
         decimals: 18,
         name: "Ethereum".to_string(),
@@ -0,0 +1,192 @@
+//! Shared connections, rate limiting and progress reporting for the supply crawl, which makes several queries for
+//! every account on chain and would otherwise overwhelm a public node
+
+use crate::history::get_current_timestamp;
+use cosmos_sdk_proto_althea::cosmos::auth::v1beta1::query_client::QueryClient as AuthQueryClient;
+use cosmos_sdk_proto_althea::cosmos::bank::v1beta1::query_client::QueryClient as BankQueryClient;
+use cosmos_sdk_proto_althea::cosmos::distribution::v1beta1::query_client::QueryClient as DistQueryClient;
+use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::query_client::QueryClient as StakingQueryClient;
+use deep_space::error::CosmosGrpcError;
+use log::info;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::time::sleep_until;
+use tonic::transport::channel::Channel;
+
+/// The clients needed to query an account's balances, cloning them shares the underlying connection
+#[derive(Clone)]
+pub struct QueryClients {
+    pub auth: AuthQueryClient<Channel>,
+    pub bank: BankQueryClient<Channel>,
+    pub dist: DistQueryClient<Channel>,
+    pub staking: StakingQueryClient<Channel>,
+}
+
+impl QueryClients {
+    pub async fn connect(url: &str) -> Result<Self, CosmosGrpcError> {
+        let channel = Channel::from_shared(url.to_string())
+            .map_err(|e| CosmosGrpcError::BadInput(format!("Invalid gRPC url {url}: {e}")))?
+            .connect()
+            .await?;
+        Ok(QueryClients {
+            auth: AuthQueryClient::new(channel.clone()),
+            bank: BankQueryClient::new(channel.clone()),
+            dist: DistQueryClient::new(channel.clone()),
+            staking: StakingQueryClient::new(channel),
+        })
+    }
+}
+
+/// A fixed set of connections handed out in turn, so the number of connections to the node doesn't grow with
+/// the number of batches. Connections are spread across every given node
+pub struct ClientPool {
+    clients: Vec<QueryClients>,
+    next: AtomicUsize,
+}
+
+impl ClientPool {
+    pub async fn connect(urls: &[String], size: usize) -> Result<Self, CosmosGrpcError> {
+        let mut clients = Vec::new();
+        for url in urls.iter().cycle().take(size.max(1)) {
+            clients.push(QueryClients::connect(url).await?);
+        }
+        Ok(ClientPool {
+            clients,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn get(&self) -> QueryClients {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.clients.len();
+        self.clients[index].clone()
+    }
+}
+
+/// Spaces out requests so that no more than a fixed number are made per second across every batch
+pub struct RateLimiter {
+    /// Time between requests, None if requests are not limited
+    interval: Option<Duration>,
+    /// The earliest time the next request may be made
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `max_per_second` requests per second, 0 disables the limit
+    pub fn new(max_per_second: u64) -> Self {
+        RateLimiter {
+            interval: match max_per_second {
+                0 => None,
+                v => Some(Duration::from_secs_f64(1.0 / v as f64)),
+            },
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until `requests` requests may be made
+    pub async fn acquire(&self, requests: u32) {
+        if let Some(slot) = self.reserve(Instant::now(), requests) {
+            sleep_until(slot.into()).await;
+        }
+    }
+
+    /// Reserves the next `requests` slots, returning when the first of them starts
+    fn reserve(&self, now: Instant, requests: u32) -> Option<Instant> {
+        let interval = self.interval?;
+        let mut next = self.next.lock().unwrap();
+        let slot = (*next).max(now);
+        *next = slot + interval * requests;
+        Some(slot)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CrawlStatus {
+    /// Unix timestamp of when the current or last full crawl started
+    pub started: u64,
+    /// Unix timestamp of when the last full crawl finished, None while it is running
+    pub finished: Option<u64>,
+    pub accounts_done: u64,
+    pub accounts_total: u64,
+}
+
+lazy_static! {
+    /// Chain id -> crawl status
+    static ref CRAWL_STATUS: Arc<RwLock<HashMap<String, CrawlStatus>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+}
+
+pub fn get_crawl_status(chain_id: &str) -> Option<CrawlStatus> {
+    CRAWL_STATUS.read().unwrap().get(chain_id).cloned()
+}
+
+pub fn start_crawl_status(chain_id: &str, accounts_total: u64) {
+    let mut r = CRAWL_STATUS.write().unwrap();
+    r.insert(
+        chain_id.to_string(),
+        CrawlStatus {
+            started: get_current_timestamp(),
+            finished: None,
+            accounts_done: 0,
+            accounts_total,
+        },
+    );
+}
+
+/// Sets the number of accounts once the node has reported it
+pub fn set_crawl_total(chain_id: &str, accounts_total: u64) {
+    let mut r = CRAWL_STATUS.write().unwrap();
+    if let Some(status) = r.get_mut(chain_id) {
+        status.accounts_total = accounts_total;
+    }
+}
+
+pub fn record_crawl_progress(chain_id: &str, accounts: u64) {
+    let mut r = CRAWL_STATUS.write().unwrap();
+    if let Some(status) = r.get_mut(chain_id) {
+        status.accounts_done += accounts;
+        info!(
+            "Crawled {}/{} accounts on {}",
+            status.accounts_done, status.accounts_total, chain_id
+        );
+    }
+}
+
+pub fn finish_crawl_status(chain_id: &str) {
+    let mut r = CRAWL_STATUS.write().unwrap();
+    if let Some(status) = r.get_mut(chain_id) {
+        status.finished = Some(get_current_timestamp());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_reserve() {
+        let limiter = RateLimiter::new(10);
+        let now = Instant::now();
+        assert_eq!(limiter.reserve(now, 4), Some(now));
+        assert_eq!(
+            limiter.reserve(now, 1),
+            Some(now + Duration::from_millis(400))
+        );
+        // slots that have already passed are not reused
+        let later = now + Duration::from_secs(2);
+        assert_eq!(limiter.reserve(later, 1), Some(later));
+
+        let unlimited = RateLimiter::new(0);
+        assert_eq!(unlimited.reserve(now, 100), None);
+
+        // rates that don't fit in a u32 are not truncated
+        let fast = RateLimiter::new(u32::MAX as u64 + 1);
+        assert!(fast.interval.unwrap() < Duration::from_nanos(1));
+        assert_eq!(
+            RateLimiter::new(3).interval,
+            Some(Duration::from_nanos(333_333_333))
+        );
+    }
+}
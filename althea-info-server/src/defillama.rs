@@ -0,0 +1,136 @@
+//! Formats TVL data in the shape expected by the DefiLlama adapter so that the adapter itself
+//! can be a thin fetch of these endpoints
+
+use crate::tvl::{TokenAmount, Tvl};
+use num256::Uint256;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DefiLlamaTvl {
+    /// Unix timestamp in seconds of when the underlying TVL was computed
+    pub timestamp: u64,
+    /// Decimal adjusted balances keyed by `coingecko:<id>`
+    pub balances: BTreeMap<String, String>,
+    /// Raw balances (not decimal adjusted) keyed by `ethereum:<erc20 address>`, only includes
+    /// tokens which have an Ethereum address
+    pub ethereum_balances: BTreeMap<String, Uint256>,
+}
+
+pub fn tvl_to_defillama(tvl: &Tvl, timestamp: u64) -> DefiLlamaTvl {
+    let mut raw_balances: BTreeMap<String, (u32, Uint256)> = BTreeMap::new();
+    let mut ethereum_balances: BTreeMap<String, Uint256> = BTreeMap::new();
+
+    let all_tokens = std::iter::once(&tvl.althea_on_chain)
+        .chain(tvl.ibc_tokens_on_chain.iter())
+        .chain(tvl.althea_native_erc20s_on_chain.iter());
+    for TokenAmount { token, amount } in all_tokens {
+        // tokens sharing a coingecko id are the same asset, possibly with different decimals on each chain
+        let key = format!("coingecko:{}", token.coingecko_id);
+        let entry = raw_balances
+            .entry(key)
+            .or_insert((token.decimals, 0u8.into()));
+        add_scaled(entry, token.decimals, *amount);
+
+        if let Some(eth_address) = token.eth_address {
+            let key = format!("ethereum:{}", eth_address.to_string().to_lowercase());
+            *ethereum_balances.entry(key).or_insert(0u8.into()) += *amount;
+        }
+    }
+
+    let balances = raw_balances
+        .into_iter()
+        .map(|(k, (decimals, amount))| (k, to_decimal_string(amount, decimals)))
+        .collect();
+
+    DefiLlamaTvl {
+        timestamp,
+        balances,
+        ethereum_balances,
+    }
+}
+
+/// Adds a raw amount with `decimals` decimal places to a (decimals, raw amount) total, rescaling the total to the
+/// larger number of decimal places so that nothing is lost
+fn add_scaled(total: &mut (u32, Uint256), decimals: u32, amount: Uint256) {
+    let pow10 = |n: u32| (0..n).fold(Uint256::from(1u8), |acc, _| acc * 10u8.into());
+    if decimals > total.0 {
+        total.1 *= pow10(decimals - total.0);
+        total.0 = decimals;
+    }
+    total.1 += amount * pow10(total.0 - decimals);
+}
+
+/// Converts a raw integer token amount into a decimal string with the given number of decimal places,
+/// this is done on the string representation to avoid any loss of precision for large values
+pub fn to_decimal_string(amount: Uint256, decimals: u32) -> String {
+    let decimals = decimals as usize;
+    let digits = amount.to_string();
+    if decimals == 0 {
+        return digits;
+    }
+    let digits = format!("{digits:0>width$}", width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{whole}.{fraction}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_decimal_string() {
+        assert_eq!(to_decimal_string(0u8.into(), 18), "0");
+        assert_eq!(to_decimal_string(1u8.into(), 6), "0.000001");
+        assert_eq!(to_decimal_string(1_500_000u32.into(), 6), "1.5");
+        assert_eq!(to_decimal_string(2_000_000u32.into(), 6), "2");
+        assert_eq!(to_decimal_string(123u8.into(), 0), "123");
+        assert_eq!(
+            to_decimal_string(2489386289699730123456789u128.into(), 18),
+            "2489386.289699730123456789"
+        );
+    }
+
+    #[test]
+    fn test_merged_decimals() {
+        use crate::config::get_tokens;
+
+        let tokens = get_tokens();
+        let althea = tokens["ALTHEA"].clone();
+        // the same asset as an IBC token with 6 decimals
+        let mut ibc_usdc = tokens["USDC"].clone();
+        // and as an EVM token with 18 decimals
+        let mut evm_usdc = ibc_usdc.clone();
+        ibc_usdc.decimals = 6;
+        evm_usdc.decimals = 18;
+        evm_usdc.eth_address = None;
+        let tvl = Tvl {
+            althea_on_chain: TokenAmount {
+                token: althea,
+                amount: 0u8.into(),
+            },
+            ibc_tokens_on_chain: vec![TokenAmount {
+                token: ibc_usdc.clone(),
+                amount: 1_500_000u32.into(),
+            }],
+            althea_native_erc20s_on_chain: vec![TokenAmount {
+                token: evm_usdc,
+                amount: 2_250_000_000_000_000_000u128.into(),
+            }],
+            bridged_out: Vec::new(),
+            unregistered_ibc_tokens_on_chain: Vec::new(),
+        };
+        let defillama = tvl_to_defillama(&tvl, 0);
+        let key = format!("coingecko:{}", ibc_usdc.coingecko_id);
+        assert_eq!(defillama.balances[&key], "3.75");
+
+        let mut total = (18, Uint256::from(1u8));
+        add_scaled(&mut total, 6, 1u8.into());
+        assert_eq!(total, (18, 1_000_000_000_001u64.into()));
+    }
+}
@@ -1,61 +1,137 @@
-use actix_web::{get, HttpResponse, Responder};
+use actix_web::{get, http::header, web, HttpRequest, HttpResponse, Responder};
 use log::error;
+use serde::Deserialize;
 
-use crate::{total_suppy::get_supply_info, tvl::get_unpriced_tvl, ALTHEA_NODE_GRPC};
+use crate::{
+    accounts::{get_account_stats, AccountStats, ACCOUNT_STATS_HISTORY},
+    chains::{get_chain, get_chains, is_althea, ALTHEA_CHAIN_ID},
+    community_pool::{get_community_pool_info, CommunityPoolFlows, COMMUNITY_POOL_HISTORY},
+    defillama::tvl_to_defillama,
+    events::event_stream,
+    evm_fees::{get_evm_index, query_evm_activity, query_evm_fees},
+    governance::{compute_participation, get_governance_info},
+    history::{find_snapshot_at, get_current_timestamp, load_snapshots, Snapshot},
+    http_cache::cached_json,
+    ibc_denoms::get_unregistered_assets,
+    inflation::{
+        get_inflation_info, get_staking_apr, InflationInfo, StakingApr, INFLATION_HISTORY,
+        STAKING_APR_HISTORY,
+    },
+    microtx::query_microtx_volume,
+    module_accounts::get_module_accounts_info,
+    settings::get_settings,
+    staking::get_staking_info,
+    status::get_status,
+    supply_report::get_supply_report,
+    total_suppy::{check_supply_freshness, get_supply_info, ChainTotalSupplyNumbers},
+    tvl::{get_total_supply, get_unpriced_chain_tvl, get_unpriced_tvl, Tvl, TVL_HISTORY},
+    tx_indexer::get_tx_index,
+    ALTHEA_EVM_RPC, ALTHEA_NODE_GRPC,
+};
 
 #[get("/total_supply")]
-async fn endpoint_get_total_supply() -> impl Responder {
+async fn endpoint_get_total_supply(req: HttpRequest) -> impl Responder {
     // if we have already computed supply info return it, if not return an error
-    match get_supply_info() {
-        Some(v) => HttpResponse::Ok().json(v.total_supply),
+    match get_supply_info(ALTHEA_CHAIN_ID) {
+        Some(v) => cached_json(&req, &v.total_supply, v.timestamp),
         None => HttpResponse::InternalServerError()
             .json("Info not yet generated, please query in 5 minutes"),
     }
 }
 
-/// If the liquid supply is lower than this value it's stale or otherwise invalid and we should
-/// return an error.
-pub const SUPPLY_CHECKPOINT: u128 = 500000000000000;
-#[get("/total_liquid_supply")]
-async fn endpoint_get_total_liquid_supply() -> impl Responder {
+/// Returns the reason supply numbers should not be served if they are stale
+fn supply_freshness(supply: &ChainTotalSupplyNumbers) -> Result<(), String> {
+    let settings = get_settings();
+    check_supply_freshness(
+        supply,
+        get_current_timestamp(),
+        settings.max_block_age,
+        settings.max_supply_age,
+    )
+}
+
+fn total_liquid_supply_response(req: &HttpRequest, chain_id: &str) -> HttpResponse {
     // if we have already computed supply info return it, if not return an error
-    match get_supply_info() {
-        Some(v) => {
-            if v.total_liquid_supply > SUPPLY_CHECKPOINT.into() {
-                HttpResponse::Ok().json(v.total_liquid_supply)
-            } else {
-                error!("Invalid supply data, got total liquid supply of {:#?}", v);
-                HttpResponse::InternalServerError()
-                    .json("Invalid supply data, Althea fullnode is stale")
+    match get_supply_info(chain_id) {
+        Some(v) => match supply_freshness(&v) {
+            Ok(()) => cached_json(req, &v.total_liquid_supply, v.timestamp),
+            Err(reason) => {
+                error!("{}", reason);
+                HttpResponse::InternalServerError().json(reason)
             }
-        }
+        },
         None => HttpResponse::InternalServerError()
             .json("Info not yet generated, please query in 5 minutes"),
     }
 }
 
-#[get("/supply_info")]
-async fn endpoint_get_all_supply_info() -> impl Responder {
+fn supply_info_response(req: &HttpRequest, chain_id: &str) -> HttpResponse {
     // if we have already computed supply info return it, if not return an error
-    match get_supply_info() {
-        Some(v) => {
-            if v.total_liquid_supply > SUPPLY_CHECKPOINT.into() {
-                HttpResponse::Ok().json(v)
-            } else {
-                error!("Invalid supply data, got total liquid supply of {:#?}", v);
-                HttpResponse::InternalServerError()
-                    .json("Invalid supply data, Althea fullnode is stale")
+    match get_supply_info(chain_id) {
+        Some(v) => match supply_freshness(&v) {
+            Ok(()) => cached_json(req, &v, v.timestamp),
+            Err(reason) => {
+                error!("{}", reason);
+                HttpResponse::InternalServerError().json(reason)
             }
-        }
+        },
         None => HttpResponse::InternalServerError()
             .json("Info not yet generated, please query in 5 minutes"),
     }
 }
 
+/// When the supply info, which the module accounts are published with, was computed
+fn supply_timestamp(chain_id: &str) -> u64 {
+    get_supply_info(chain_id).map_or(0, |v| v.timestamp)
+}
+
+fn module_accounts_response(req: &HttpRequest, chain_id: &str) -> HttpResponse {
+    match get_module_accounts_info(chain_id) {
+        Some(v) => cached_json(req, &v, supply_timestamp(chain_id)),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+fn supply_report_response(req: &HttpRequest, chain_id: &str) -> HttpResponse {
+    match get_supply_report(chain_id) {
+        Some(v) => cached_json(req, &v, v.timestamp),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+fn unknown_chain_response(chain_id: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(format!("Unknown chain {chain_id}, see /chains"))
+}
+
+#[get("/total_liquid_supply")]
+async fn endpoint_get_total_liquid_supply(req: HttpRequest) -> impl Responder {
+    total_liquid_supply_response(&req, ALTHEA_CHAIN_ID)
+}
+
+#[get("/supply_info")]
+async fn endpoint_get_all_supply_info(req: HttpRequest) -> impl Responder {
+    supply_info_response(&req, ALTHEA_CHAIN_ID)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TvlQuery {
+    /// If true IBC tokens which are not in the token registry are included in the TVL
+    #[serde(default)]
+    pub include_unregistered: bool,
+}
+
 #[get("/unpriced_tvl")]
-async fn endpoint_get_unpriced_tvl() -> impl Responder {
+async fn endpoint_get_unpriced_tvl(query: web::Query<TvlQuery>) -> impl Responder {
     // Try to get the TVL, on failure return an error
-    match get_unpriced_tvl(ALTHEA_NODE_GRPC.to_string()).await {
+    match get_unpriced_tvl(
+        ALTHEA_NODE_GRPC.to_string(),
+        ALTHEA_EVM_RPC.to_string(),
+        query.include_unregistered,
+    )
+    .await
+    {
         Ok(v) => HttpResponse::Ok().json(v),
         Err(e) => {
             error!("Error getting unpriced TVL: {:#?}", e);
@@ -63,3 +139,352 @@ async fn endpoint_get_unpriced_tvl() -> impl Responder {
         }
     }
 }
+
+#[get("/defillama/tvl")]
+async fn endpoint_get_defillama_tvl() -> impl Responder {
+    match get_unpriced_tvl(
+        ALTHEA_NODE_GRPC.to_string(),
+        ALTHEA_EVM_RPC.to_string(),
+        false,
+    )
+    .await
+    {
+        Ok(v) => HttpResponse::Ok().json(tvl_to_defillama(&v, get_current_timestamp())),
+        Err(e) => {
+            error!("Error getting unpriced TVL: {:#?}", e);
+            HttpResponse::InternalServerError().json("Error getting unpriced TVL")
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoricalQuery {
+    /// Unix timestamp in seconds, if provided only the most recent snapshot at or before
+    /// this time is returned
+    pub timestamp: Option<u64>,
+}
+
+#[get("/defillama/tvl/historical")]
+async fn endpoint_get_defillama_historical_tvl(
+    req: HttpRequest,
+    query: web::Query<HistoricalQuery>,
+) -> impl Responder {
+    let snapshots: Vec<Snapshot<Tvl>> = load_snapshots(TVL_HISTORY);
+    match query.timestamp {
+        Some(timestamp) => match find_snapshot_at(&snapshots, timestamp) {
+            Some(s) => cached_json(&req, &tvl_to_defillama(&s.data, s.timestamp), s.timestamp),
+            None => HttpResponse::NotFound().json("No TVL snapshot at or before this timestamp"),
+        },
+        None => cached_json(
+            &req,
+            &snapshots
+                .iter()
+                .map(|s| tvl_to_defillama(&s.data, s.timestamp))
+                .collect::<Vec<_>>(),
+            last_snapshot_timestamp(&snapshots),
+        ),
+    }
+}
+
+/// When the newest snapshot in a history was taken, 0 if there are none
+fn last_snapshot_timestamp<T>(snapshots: &[Snapshot<T>]) -> u64 {
+    snapshots.last().map_or(0, |s| s.timestamp)
+}
+
+#[get("/unregistered_assets")]
+async fn endpoint_get_unregistered_assets() -> impl Responder {
+    let supply = match get_total_supply(ALTHEA_NODE_GRPC).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Error getting total supply: {:#?}", e);
+            return HttpResponse::InternalServerError().json("Error getting total supply");
+        }
+    };
+    match get_unregistered_assets(&supply, ALTHEA_NODE_GRPC).await {
+        Ok(v) => HttpResponse::Ok().json(v),
+        Err(e) => {
+            error!("Error getting unregistered assets: {:#?}", e);
+            HttpResponse::InternalServerError().json("Error getting unregistered assets")
+        }
+    }
+}
+
+#[get("/staking")]
+async fn endpoint_get_staking_info() -> impl Responder {
+    match get_staking_info() {
+        Some(v) => HttpResponse::Ok().json(v),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+#[get("/inflation")]
+async fn endpoint_get_inflation() -> impl Responder {
+    match get_inflation_info() {
+        Some(v) => HttpResponse::Ok().json(v),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+#[get("/inflation/history")]
+async fn endpoint_get_inflation_history(req: HttpRequest) -> impl Responder {
+    let snapshots: Vec<Snapshot<InflationInfo>> = load_snapshots(INFLATION_HISTORY);
+    cached_json(&req, &snapshots, last_snapshot_timestamp(&snapshots))
+}
+
+#[get("/staking_apr")]
+async fn endpoint_get_staking_apr() -> impl Responder {
+    match get_staking_apr() {
+        Some(v) => HttpResponse::Ok().json(v),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+#[get("/staking_apr/history")]
+async fn endpoint_get_staking_apr_history(req: HttpRequest) -> impl Responder {
+    let snapshots: Vec<Snapshot<StakingApr>> = load_snapshots(STAKING_APR_HISTORY);
+    cached_json(&req, &snapshots, last_snapshot_timestamp(&snapshots))
+}
+
+#[get("/governance/proposals")]
+async fn endpoint_get_governance_proposals() -> impl Responder {
+    match get_governance_info() {
+        Some(v) => HttpResponse::Ok().json(v.proposals),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ParticipationQuery {
+    /// The number of most recent proposals to compute participation over
+    pub proposals: Option<usize>,
+}
+
+#[get("/governance/participation")]
+async fn endpoint_get_governance_participation(
+    query: web::Query<ParticipationQuery>,
+) -> impl Responder {
+    const DEFAULT_PROPOSALS: usize = 10;
+    match get_governance_info() {
+        Some(v) => HttpResponse::Ok().json(compute_participation(
+            &v,
+            query.proposals.unwrap_or(DEFAULT_PROPOSALS),
+        )),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+/// Rejects a from/to query where `from` is after `to`
+fn check_range(from: Option<u64>, to: Option<u64>) -> Result<(), HttpResponse> {
+    match (from, to) {
+        (Some(from), Some(to)) if from > to => {
+            Err(HttpResponse::BadRequest().json("from must not be after to"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MicrotxVolumeQuery {
+    /// Unix timestamp in seconds, rounded down to the start of its day
+    pub from: Option<u64>,
+    /// Unix timestamp in seconds
+    pub to: Option<u64>,
+    pub denom: Option<String>,
+}
+
+#[get("/microtx/volume")]
+async fn endpoint_get_microtx_volume(query: web::Query<MicrotxVolumeQuery>) -> impl Responder {
+    if let Err(e) = check_range(query.from, query.to) {
+        return e;
+    }
+    match get_tx_index() {
+        Some(v) => HttpResponse::Ok().json(query_microtx_volume(
+            &v.microtx_volume,
+            v.last_indexed_height,
+            query.from,
+            query.to,
+            query.denom.as_deref(),
+        )),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DayRangeQuery {
+    /// Unix timestamp in seconds, rounded down to the start of its day
+    pub from: Option<u64>,
+    /// Unix timestamp in seconds
+    pub to: Option<u64>,
+}
+
+#[get("/evm/fees")]
+async fn endpoint_get_evm_fees(query: web::Query<DayRangeQuery>) -> impl Responder {
+    if let Err(e) = check_range(query.from, query.to) {
+        return e;
+    }
+    match get_evm_index() {
+        Some(v) => HttpResponse::Ok().json(query_evm_fees(&v, query.from, query.to)),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+#[get("/evm/activity")]
+async fn endpoint_get_evm_activity(query: web::Query<DayRangeQuery>) -> impl Responder {
+    if let Err(e) = check_range(query.from, query.to) {
+        return e;
+    }
+    match get_evm_index() {
+        Some(v) => HttpResponse::Ok().json(query_evm_activity(&v, query.from, query.to)),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+#[get("/accounts/stats")]
+async fn endpoint_get_account_stats() -> impl Responder {
+    match get_account_stats() {
+        Some(v) => HttpResponse::Ok().json(v),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+#[get("/accounts/stats/history")]
+async fn endpoint_get_account_stats_history(req: HttpRequest) -> impl Responder {
+    let snapshots: Vec<Snapshot<AccountStats>> = load_snapshots(ACCOUNT_STATS_HISTORY);
+    cached_json(&req, &snapshots, last_snapshot_timestamp(&snapshots))
+}
+
+#[get("/community_pool")]
+async fn endpoint_get_community_pool() -> impl Responder {
+    match get_community_pool_info() {
+        Some(v) => HttpResponse::Ok().json(v),
+        None => HttpResponse::InternalServerError()
+            .json("Info not yet generated, please query in 5 minutes"),
+    }
+}
+
+#[get("/community_pool/history")]
+async fn endpoint_get_community_pool_history(req: HttpRequest) -> impl Responder {
+    let snapshots: Vec<Snapshot<CommunityPoolFlows>> = load_snapshots(COMMUNITY_POOL_HISTORY);
+    cached_json(&req, &snapshots, last_snapshot_timestamp(&snapshots))
+}
+
+#[get("/module_accounts")]
+async fn endpoint_get_module_accounts(req: HttpRequest) -> impl Responder {
+    module_accounts_response(&req, ALTHEA_CHAIN_ID)
+}
+
+#[get("/supply_report")]
+async fn endpoint_get_supply_report(req: HttpRequest) -> impl Responder {
+    supply_report_response(&req, ALTHEA_CHAIN_ID)
+}
+
+#[get("/status")]
+async fn endpoint_get_status() -> impl Responder {
+    HttpResponse::Ok().json(get_status())
+}
+
+#[get("/chains")]
+async fn endpoint_get_chains() -> impl Responder {
+    HttpResponse::Ok().json(get_chains())
+}
+
+#[get("/chains/{chain_id}/supply_info")]
+async fn endpoint_get_chain_supply_info(
+    req: HttpRequest,
+    chain_id: web::Path<String>,
+) -> impl Responder {
+    match get_chain(&chain_id) {
+        Some(chain) => supply_info_response(&req, &chain.chain_id),
+        None => unknown_chain_response(&chain_id),
+    }
+}
+
+#[get("/chains/{chain_id}/total_liquid_supply")]
+async fn endpoint_get_chain_total_liquid_supply(
+    req: HttpRequest,
+    chain_id: web::Path<String>,
+) -> impl Responder {
+    match get_chain(&chain_id) {
+        Some(chain) => total_liquid_supply_response(&req, &chain.chain_id),
+        None => unknown_chain_response(&chain_id),
+    }
+}
+
+#[get("/chains/{chain_id}/module_accounts")]
+async fn endpoint_get_chain_module_accounts(
+    req: HttpRequest,
+    chain_id: web::Path<String>,
+) -> impl Responder {
+    match get_chain(&chain_id) {
+        Some(chain) => module_accounts_response(&req, &chain.chain_id),
+        None => unknown_chain_response(&chain_id),
+    }
+}
+
+#[get("/chains/{chain_id}/supply_report")]
+async fn endpoint_get_chain_supply_report(
+    req: HttpRequest,
+    chain_id: web::Path<String>,
+) -> impl Responder {
+    match get_chain(&chain_id) {
+        Some(chain) => supply_report_response(&req, &chain.chain_id),
+        None => unknown_chain_response(&chain_id),
+    }
+}
+
+#[get("/chains/{chain_id}/unpriced_tvl")]
+async fn endpoint_get_chain_unpriced_tvl(chain_id: web::Path<String>) -> impl Responder {
+    let chain = match get_chain(&chain_id) {
+        Some(chain) => chain,
+        None => return unknown_chain_response(&chain_id),
+    };
+    // Althea L1 has its own TVL breakdown including EVM tokens and IBC escrows
+    let result = if is_althea(chain) {
+        get_unpriced_tvl(
+            ALTHEA_NODE_GRPC.to_string(),
+            ALTHEA_EVM_RPC.to_string(),
+            false,
+        )
+        .await
+        .map(|v| HttpResponse::Ok().json(v))
+    } else {
+        get_unpriced_chain_tvl(chain)
+            .await
+            .map(|v| HttpResponse::Ok().json(v))
+    };
+    match result {
+        Ok(v) => v,
+        Err(e) => {
+            error!(
+                "Error getting unpriced TVL for {}: {:#?}",
+                chain.chain_id, e
+            );
+            HttpResponse::InternalServerError().json("Error getting unpriced TVL")
+        }
+    }
+}
+
+#[get("/events")]
+async fn endpoint_get_events(req: HttpRequest) -> impl Responder {
+    // browsers send the id of the last event received when reconnecting
+    let last_event_id = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok());
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // the compression middleware would hold back events until it had filled a block
+        .insert_header(header::ContentEncoding::Identity)
+        .streaming(event_stream(last_event_id))
+}
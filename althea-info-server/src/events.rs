@@ -0,0 +1,179 @@
+//! Pushes newly published supply info and TVL snapshots to clients as Server-Sent Events, so that dashboards can
+//! update live instead of polling. Recent events are kept in memory so a reconnecting client can resume from the
+//! last event it received using the Last-Event-ID header
+
+use crate::total_suppy::ChainTotalSupplyNumbers;
+use actix_web::web::Bytes;
+use futures::stream::{self, Stream, StreamExt};
+use log::error;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError, Receiver, Sender};
+use tokio::time::timeout;
+
+/// How many recent events are kept for clients resuming with Last-Event-ID
+const MAX_RECENT_EVENTS: usize = 256;
+/// How long a stream may be idle before a comment is sent to keep proxies from closing it
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// New supply info for a chain, see SupplyInfoEvent
+    SupplyInfo,
+    /// A new hourly Althea L1 TVL snapshot
+    Tvl,
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::SupplyInfo => "supply_info",
+            EventKind::Tvl => "tvl",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SupplyInfoEvent<'a> {
+    pub chain_id: &'a str,
+    pub supply: &'a ChainTotalSupplyNumbers,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub id: u64,
+    pub kind: EventKind,
+    /// Single line json
+    pub data: String,
+}
+
+impl Event {
+    /// Formats the event for the text/event-stream wire format
+    pub fn to_sse(&self) -> String {
+        format!(
+            "id: {}\nevent: {}\ndata: {}\n\n",
+            self.id,
+            self.kind.name(),
+            self.data
+        )
+    }
+}
+
+pub struct EventLog {
+    next_id: u64,
+    recent: VecDeque<Event>,
+    sender: Sender<Event>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        EventLog {
+            next_id: 1,
+            recent: VecDeque::new(),
+            sender: broadcast::channel(MAX_RECENT_EVENTS).0,
+        }
+    }
+
+    pub fn publish(&mut self, kind: EventKind, data: String) -> Event {
+        let event = Event {
+            id: self.next_id,
+            kind,
+            data,
+        };
+        self.next_id += 1;
+        self.recent.push_back(event.clone());
+        if self.recent.len() > MAX_RECENT_EVENTS {
+            self.recent.pop_front();
+        }
+        // fails only if nobody is listening
+        let _ = self.sender.send(event.clone());
+        event
+    }
+
+    /// Returns the recent events after `last_event_id` and a receiver for every event after those. Without a last
+    /// event id nothing is replayed, an id this log never handed out (from before a restart) replays every recent
+    /// event
+    pub fn subscribe(&self, last_event_id: Option<u64>) -> (Vec<Event>, Receiver<Event>) {
+        let replay = match last_event_id {
+            None => Vec::new(),
+            Some(id) if id < self.next_id => {
+                self.recent.iter().filter(|e| e.id > id).cloned().collect()
+            }
+            Some(_) => self.recent.iter().cloned().collect(),
+        };
+        (replay, self.sender.subscribe())
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+lazy_static! {
+    static ref EVENTS: Arc<RwLock<EventLog>> = Arc::new(RwLock::new(EventLog::new()));
+}
+
+/// Sends an event to every connected client
+pub fn publish_event<T: Serialize>(kind: EventKind, data: &T) {
+    match serde_json::to_string(data) {
+        Ok(data) => {
+            EVENTS.write().unwrap().publish(kind, data);
+        }
+        Err(e) => error!("Failed to serialize {} event with {:?}", kind.name(), e),
+    }
+}
+
+/// A text/event-stream body replaying the events after `last_event_id` followed by every new event. The stream
+/// ends if the client falls too far behind, it will then reconnect and resume from the recent events
+pub fn event_stream(last_event_id: Option<u64>) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    // publishing takes the write lock, so no event is missed or sent twice between the replay and the receiver
+    let (replay, receiver) = EVENTS.read().unwrap().subscribe(last_event_id);
+    let replay = stream::iter(replay.into_iter().map(|e| Ok(Bytes::from(e.to_sse()))));
+    let live = stream::unfold(receiver, |mut receiver| async move {
+        match timeout(KEEP_ALIVE_INTERVAL, receiver.recv()).await {
+            Ok(Ok(event)) => Some((Ok(Bytes::from(event.to_sse())), receiver)),
+            Ok(Err(RecvError::Lagged(_))) | Ok(Err(RecvError::Closed)) => None,
+            Err(_) => Some((Ok(Bytes::from_static(b": keep-alive\n\n")), receiver)),
+        }
+    });
+    replay.chain(live)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_log_resume() {
+        let mut log = EventLog::new();
+        for i in 0..3 {
+            log.publish(EventKind::SupplyInfo, i.to_string());
+        }
+
+        let (replay, _) = log.subscribe(None);
+        assert!(replay.is_empty());
+        let (replay, _) = log.subscribe(Some(1));
+        assert_eq!(replay.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2, 3]);
+        let (replay, _) = log.subscribe(Some(3));
+        assert!(replay.is_empty());
+        // an id from before a restart
+        let (replay, mut receiver) = log.subscribe(Some(100));
+        assert_eq!(replay.len(), 3);
+
+        let event = log.publish(EventKind::Tvl, "{}".to_string());
+        assert_eq!(receiver.try_recv().unwrap(), event);
+        assert_eq!(event.to_sse(), "id: 4\nevent: tvl\ndata: {}\n\n");
+
+        for i in 0..MAX_RECENT_EVENTS {
+            log.publish(EventKind::SupplyInfo, i.to_string());
+        }
+        let (replay, _) = log.subscribe(Some(0));
+        assert_eq!(replay.len(), MAX_RECENT_EVENTS);
+        assert_eq!(replay[0].id, 5);
+    }
+}
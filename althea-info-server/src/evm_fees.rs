@@ -0,0 +1,421 @@
+//! EVM transaction fee and activity statistics. Blocks are read from the Althea L1 EVM JSON-RPC and aggregated
+//! per day, the index is persisted after every batch of blocks so a restart resumes where it left off
+
+use crate::{
+    history::{day_start, load_state, save_state},
+    staking::ratio,
+    tx_indexer::get_earliest_height,
+    ALTHEA_EVM_RPC, ALTHEA_NODE_GRPC, REQUEST_TIMEOUT,
+};
+use clarity::Address;
+use cosmos_sdk_proto_althea::cosmos::base::tendermint::v1beta1::service_client::ServiceClient as TendermintServiceClient;
+use log::{error, info};
+use num256::Uint256;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::sleep;
+use web30::client::Web3;
+use web30::jsonrpc::client::HttpClient;
+use web30::types::{Block, TransactionResponse};
+
+/// How long to wait before checking for new blocks once the index has caught up
+const LOOP_TIME: Duration = Duration::from_secs(30);
+/// The most blocks indexed before the index is saved
+const BLOCKS_PER_BATCH: u64 = 500;
+/// The name of the state file the index is stored in
+const EVM_INDEX_STATE: &str = "evm_index";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvmDay {
+    pub tx_count: u64,
+    pub gas_used: Uint256,
+    /// gas_used * base fee, the portion of fees that would be burned under EIP-1559
+    pub base_fees: Uint256,
+    /// Fees paid above the base fee
+    pub priority_fees: Uint256,
+    /// base_fees + priority_fees, the total paid by senders
+    pub total_fees: Uint256,
+    #[serde(default)]
+    pub unique_senders: u64,
+    /// Only kept for the latest day, earlier days are reduced to unique_senders once the indexer moves past them
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub senders: BTreeSet<Address>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvmIndex {
+    /// The last block included in the index, zero if nothing has been indexed
+    pub last_indexed_block: u64,
+    /// Start of day unix timestamp (UTC) -> stats
+    pub days: BTreeMap<u64, EvmDay>,
+}
+
+impl EvmIndex {
+    /// Drops the senders of every day before the latest, keeping only their count
+    fn finish_days(&mut self) {
+        let latest = match self.days.keys().next_back() {
+            Some(v) => *v,
+            None => return,
+        };
+        for day in self.days.range_mut(..latest).map(|(_, d)| d) {
+            if !day.senders.is_empty() {
+                day.unique_senders = day.senders.len() as u64;
+                day.senders.clear();
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EvmFees {
+    /// Unix timestamp of the start of this day in UTC, null for totals
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<u64>,
+    pub gas_used: Uint256,
+    pub base_fees: Uint256,
+    pub priority_fees: Uint256,
+    pub total_fees: Uint256,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EvmActivity {
+    /// Unix timestamp of the start of this day in UTC, null for totals
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<u64>,
+    pub tx_count: u64,
+    /// Addresses which sent at least one transaction on this day, null for totals
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unique_senders: Option<u64>,
+    /// The sum of unique_senders over the days in the range, only daily sender counts are kept so a sender active
+    /// on several days is counted once for each of them. Null for days
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sender_days: Option<u64>,
+    /// total_fees / gas_used, the gas weighted average price paid in wei
+    pub average_gas_price: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EvmReport<T> {
+    /// The last block included in these statistics
+    pub last_indexed_block: u64,
+    /// Totals over every day in the requested range
+    pub total: T,
+    /// Days with at least one transaction, oldest first
+    pub days: Vec<T>,
+}
+
+/// The fields we need from eth_getTransactionReceipt, web30 does not provide this call
+#[derive(Debug, Deserialize)]
+struct TransactionReceipt {
+    #[serde(rename = "gasUsed")]
+    gas_used: Uint256,
+    /// Not returned by some nodes for legacy transactions, where it is the gas price
+    #[serde(rename = "effectiveGasPrice")]
+    effective_gas_price: Option<Uint256>,
+}
+
+lazy_static! {
+    static ref EVM_INDEX: Arc<RwLock<Option<EvmIndex>>> = Arc::new(RwLock::new(None));
+}
+
+fn set_evm_index(input: EvmIndex) {
+    let mut r = EVM_INDEX.write().unwrap();
+    *r = Some(input);
+}
+
+pub fn get_evm_index() -> Option<EvmIndex> {
+    EVM_INDEX.read().unwrap().clone()
+}
+
+pub async fn evm_indexer_worker() {
+    // after a restart the index in memory is newer than the saved one
+    if get_evm_index().is_none() {
+        if let Some(mut index) = load_state::<EvmIndex>(EVM_INDEX_STATE) {
+            info!("Resuming EVM index from block {}", index.last_indexed_block);
+            // indexes saved before senders were reduced to counts hold every day's senders
+            index.finish_days();
+            set_evm_index(index);
+        }
+    }
+
+    loop {
+        let caught_up = {
+            let mut index = get_evm_index().unwrap_or_default();
+            let start = index.last_indexed_block;
+            let res = index_evm_blocks(ALTHEA_EVM_RPC, &mut index, BLOCKS_PER_BATCH).await;
+            // blocks indexed before an error are still saved
+            if index.last_indexed_block != start {
+                info!("Indexed EVM blocks up to {}", index.last_indexed_block);
+                if let Err(e) = save_state(EVM_INDEX_STATE, &index) {
+                    error!("Failed to save EVM index with {:?}", e);
+                }
+                set_evm_index(index);
+            }
+            match res {
+                Ok(caught_up) => caught_up,
+                Err(e) => {
+                    error!("Failed to index EVM blocks with {:?}", e);
+                    true
+                }
+            }
+        };
+        if caught_up {
+            sleep(LOOP_TIME).await;
+        }
+    }
+}
+
+/// Indexes up to `max_blocks` blocks after the last indexed block, returns true if the index has reached the
+/// latest block
+pub async fn index_evm_blocks(
+    evm_rpc: &str,
+    index: &mut EvmIndex,
+    max_blocks: u64,
+) -> Result<bool, String> {
+    let web3 = Web3::new(evm_rpc, REQUEST_TIMEOUT);
+    let rpc = HttpClient::new(evm_rpc);
+
+    let latest = web3
+        .eth_block_number()
+        .await
+        .map_err(|e| format!("Failed to get latest EVM block: {e}"))?;
+    let latest = to_u64(latest)?;
+    if index.last_indexed_block == 0 {
+        // EVM block numbers are Cosmos block heights, so the node's pruning applies to both
+        let mut tmrpc = TendermintServiceClient::connect(ALTHEA_NODE_GRPC.to_string())
+            .await
+            .map_err(|e| format!("Failed to connect to tendermint gRPC endpoint: {e}"))?;
+        index.last_indexed_block = get_earliest_height(&mut tmrpc)
+            .await
+            .map_err(|e| format!("Failed to get earliest block: {e:?}"))?
+            - 1;
+    }
+
+    let end = latest.min(index.last_indexed_block + max_blocks);
+    for number in index.last_indexed_block + 1..=end {
+        let block = web3
+            .eth_get_block_by_number(number.into())
+            .await
+            .map_err(|e| format!("Failed to get EVM block {number}: {e}"))?;
+        index_evm_block(&rpc, index, block).await?;
+        index.last_indexed_block = number;
+    }
+    Ok(end == latest)
+}
+
+/// Adds every transaction in a block to the index, the index is only modified once every receipt for the
+/// block has been fetched so a failed block can be retried without double counting
+async fn index_evm_block(
+    rpc: &HttpClient,
+    index: &mut EvmIndex,
+    block: Block,
+) -> Result<(), String> {
+    let timestamp = to_u64(block.timestamp)?;
+    let base_fee = block.base_fee_per_gas.unwrap_or(0u8.into());
+
+    let mut txs = Vec::new();
+    for tx in block.transactions {
+        let (hash, from, gas_price) = match &tx {
+            TransactionResponse::Eip1559 {
+                hash,
+                from,
+                gas_price,
+                ..
+            }
+            | TransactionResponse::Eip2930 {
+                hash,
+                from,
+                gas_price,
+                ..
+            }
+            | TransactionResponse::Legacy {
+                hash,
+                from,
+                gas_price,
+                ..
+            } => (format!("0x{}", hex::encode(&**hash)), *from, *gas_price),
+        };
+        let receipt: Option<TransactionReceipt> = rpc
+            .request_method(
+                "eth_getTransactionReceipt",
+                vec![hash.clone()],
+                REQUEST_TIMEOUT,
+            )
+            .await
+            .map_err(|e| format!("Failed to get receipt for {hash}: {e}"))?;
+        let receipt = receipt.ok_or(format!("No receipt for {hash}"))?;
+        txs.push((
+            from,
+            receipt.gas_used,
+            receipt.effective_gas_price.unwrap_or(gas_price),
+        ));
+    }
+
+    let day_timestamp = day_start(timestamp);
+    let new_day = !index.days.contains_key(&day_timestamp);
+    let day = index.days.entry(day_timestamp).or_default();
+    for (from, gas_used, gas_price) in txs {
+        record_evm_tx(day, from, gas_used, gas_price, base_fee);
+    }
+    if new_day {
+        index.finish_days();
+    }
+    Ok(())
+}
+
+fn record_evm_tx(
+    day: &mut EvmDay,
+    from: Address,
+    gas_used: Uint256,
+    gas_price: Uint256,
+    base_fee: Uint256,
+) {
+    let total = gas_used * gas_price;
+    // the base fee can't exceed the price paid, but don't trust the node with a subtraction
+    let base = gas_used * base_fee.min(gas_price);
+    day.tx_count += 1;
+    day.gas_used += gas_used;
+    day.base_fees += base;
+    day.priority_fees += total - base;
+    day.total_fees += total;
+    day.senders.insert(from);
+    day.unique_senders = day.senders.len() as u64;
+}
+
+fn to_u64(input: Uint256) -> Result<u64, String> {
+    input
+        .to_string()
+        .parse()
+        .map_err(|_| format!("{input} does not fit in a u64"))
+}
+
+/// Daily fee statistics between `from` and `to` inclusive, `from` is rounded down to the start of its day
+pub fn query_evm_fees(index: &EvmIndex, from: Option<u64>, to: Option<u64>) -> EvmReport<EvmFees> {
+    let mut total = EvmFees::default();
+    let mut days = Vec::new();
+    for (day, stats) in select_days(index, from, to) {
+        total.gas_used += stats.gas_used;
+        total.base_fees += stats.base_fees;
+        total.priority_fees += stats.priority_fees;
+        total.total_fees += stats.total_fees;
+        days.push(EvmFees {
+            day: Some(day),
+            gas_used: stats.gas_used,
+            base_fees: stats.base_fees,
+            priority_fees: stats.priority_fees,
+            total_fees: stats.total_fees,
+        });
+    }
+    EvmReport {
+        last_indexed_block: index.last_indexed_block,
+        total,
+        days,
+    }
+}
+
+/// Daily activity statistics between `from` and `to` inclusive, `from` is rounded down to the start of its
+/// day
+pub fn query_evm_activity(
+    index: &EvmIndex,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> EvmReport<EvmActivity> {
+    let mut gas_used: Uint256 = 0u8.into();
+    let mut total_fees: Uint256 = 0u8.into();
+    let mut sender_days = 0;
+    let mut total = EvmActivity::default();
+    let mut days = Vec::new();
+    for (day, stats) in select_days(index, from, to) {
+        sender_days += stats.unique_senders;
+        gas_used += stats.gas_used;
+        total_fees += stats.total_fees;
+        total.tx_count += stats.tx_count;
+        days.push(EvmActivity {
+            day: Some(day),
+            tx_count: stats.tx_count,
+            unique_senders: Some(stats.unique_senders),
+            sender_days: None,
+            average_gas_price: ratio(stats.total_fees, stats.gas_used),
+        });
+    }
+    total.sender_days = Some(sender_days);
+    total.average_gas_price = ratio(total_fees, gas_used);
+    EvmReport {
+        last_indexed_block: index.last_indexed_block,
+        total,
+        days,
+    }
+}
+
+fn select_days(
+    index: &EvmIndex,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> impl Iterator<Item = (u64, &EvmDay)> {
+    let from = day_start(from.unwrap_or(0));
+    let to = to.unwrap_or(u64::MAX);
+    // BTreeMap::range panics on an inverted range
+    let selected = if from <= to {
+        Some(index.days.range(from..=to))
+    } else {
+        None
+    };
+    selected
+        .into_iter()
+        .flatten()
+        .filter(|(_, stats)| stats.tx_count > 0)
+        .map(|(day, stats)| (*day, stats))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::SECONDS_PER_DAY;
+
+    #[test]
+    fn test_evm_statistics() {
+        let a = Address::from_slice(&[1; 20]).unwrap();
+        let b = Address::from_slice(&[2; 20]).unwrap();
+        let mut index = EvmIndex::default();
+
+        let day = index.days.entry(SECONDS_PER_DAY).or_default();
+        record_evm_tx(day, a, 100u32.into(), 15u32.into(), 10u32.into());
+        record_evm_tx(day, b, 300u32.into(), 10u32.into(), 10u32.into());
+        let day = index.days.entry(SECONDS_PER_DAY * 2).or_default();
+        // a base fee above the price paid is clamped rather than underflowing
+        record_evm_tx(day, a, 100u32.into(), 5u32.into(), 10u32.into());
+        index.finish_days();
+        // the days before the latest only keep their sender count
+        assert!(index.days[&SECONDS_PER_DAY].senders.is_empty());
+        assert_eq!(index.days[&(SECONDS_PER_DAY * 2)].senders.len(), 1);
+
+        let fees = query_evm_fees(&index, None, None);
+        assert_eq!(fees.days.len(), 2);
+        assert_eq!(fees.days[0].total_fees, 4500u32.into());
+        assert_eq!(fees.days[0].base_fees, 4000u32.into());
+        assert_eq!(fees.days[0].priority_fees, 500u32.into());
+        assert_eq!(fees.total.total_fees, 5000u32.into());
+        assert_eq!(fees.total.gas_used, 500u32.into());
+
+        let activity = query_evm_activity(&index, None, None);
+        assert_eq!(activity.days[0].tx_count, 2);
+        assert_eq!(activity.days[0].unique_senders, Some(2));
+        assert_eq!(activity.days[1].unique_senders, Some(1));
+        assert_eq!(activity.days[0].sender_days, None);
+        assert_eq!(activity.total.unique_senders, None);
+        assert_eq!(activity.total.sender_days, Some(3));
+        assert_eq!(activity.total.average_gas_price, 10.0);
+
+        let later = query_evm_activity(&index, Some(SECONDS_PER_DAY * 2 + 10), None);
+        assert_eq!(later.days.len(), 1);
+        assert_eq!(later.total.tx_count, 1);
+
+        // a range ending before the day `from` is rounded down to is empty
+        let inverted = query_evm_fees(&index, Some(SECONDS_PER_DAY * 2), Some(SECONDS_PER_DAY));
+        assert!(inverted.days.is_empty());
+        let inverted = query_evm_activity(&index, Some(SECONDS_PER_DAY * 2), Some(SECONDS_PER_DAY));
+        assert!(inverted.days.is_empty());
+    }
+}
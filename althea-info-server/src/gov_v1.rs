@@ -0,0 +1,182 @@
+//! The gov v1 query service and the v1 proposal messages this server reads, which the cosmos-sdk-proto-althea
+//! version we use doesn't export. Only the fields read by this server are declared, prost skips any other fields
+//! when decoding
+
+use cosmos_sdk_proto_althea::cosmos::base::query::v1beta1::{PageRequest, PageResponse};
+use cosmos_sdk_proto_althea::cosmos::base::v1beta1::Coin;
+use prost_types::{Any, Timestamp};
+use tonic::client::Grpc;
+use tonic::codec::ProstCodec;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Response, Status};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Proposal {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(message, repeated, tag = "2")]
+    pub messages: Vec<Any>,
+    /// The same values as the v1beta1 ProposalStatus
+    #[prost(int32, tag = "3")]
+    pub status: i32,
+    /// Only set once the voting period has ended
+    #[prost(message, optional, tag = "4")]
+    pub final_tally_result: Option<TallyResult>,
+    #[prost(message, repeated, tag = "7")]
+    pub total_deposit: Vec<Coin>,
+    #[prost(message, optional, tag = "9")]
+    pub voting_end_time: Option<Timestamp>,
+    #[prost(string, tag = "10")]
+    pub metadata: String,
+    /// Added in Cosmos SDK 0.47, empty on older chains
+    #[prost(string, tag = "11")]
+    pub title: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TallyResult {
+    #[prost(string, tag = "1")]
+    pub yes_count: String,
+    #[prost(string, tag = "2")]
+    pub abstain_count: String,
+    #[prost(string, tag = "3")]
+    pub no_count: String,
+    #[prost(string, tag = "4")]
+    pub no_with_veto_count: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Vote {
+    #[prost(uint64, tag = "1")]
+    pub proposal_id: u64,
+    #[prost(string, tag = "2")]
+    pub voter: String,
+}
+
+/// Wraps a legacy proposal's content as a v1 proposal message
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgExecLegacyContent {
+    #[prost(message, optional, tag = "1")]
+    pub content: Option<Any>,
+    #[prost(string, tag = "2")]
+    pub authority: String,
+}
+
+/// Spends from the community pool, the v1 replacement for CommunityPoolSpendProposal
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MsgCommunityPoolSpend {
+    #[prost(string, tag = "1")]
+    pub authority: String,
+    #[prost(string, tag = "2")]
+    pub recipient: String,
+    #[prost(message, repeated, tag = "3")]
+    pub amount: Vec<Coin>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryProposalsRequest {
+    #[prost(int32, tag = "1")]
+    pub proposal_status: i32,
+    #[prost(string, tag = "2")]
+    pub voter: String,
+    #[prost(string, tag = "3")]
+    pub depositor: String,
+    #[prost(message, optional, tag = "4")]
+    pub pagination: Option<PageRequest>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryProposalsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub proposals: Vec<Proposal>,
+    #[prost(message, optional, tag = "2")]
+    pub pagination: Option<PageResponse>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryVotesRequest {
+    #[prost(uint64, tag = "1")]
+    pub proposal_id: u64,
+    #[prost(message, optional, tag = "2")]
+    pub pagination: Option<PageRequest>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryVotesResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub votes: Vec<Vote>,
+    #[prost(message, optional, tag = "2")]
+    pub pagination: Option<PageResponse>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryTallyResultRequest {
+    #[prost(uint64, tag = "1")]
+    pub proposal_id: u64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct QueryTallyResultResponse {
+    #[prost(message, optional, tag = "1")]
+    pub tally: Option<TallyResult>,
+}
+
+/// A client for the queries above with the same interface as the generated clients, a node without gov v1
+/// answers them with Code::Unimplemented
+#[derive(Debug, Clone)]
+pub struct QueryClient {
+    inner: Grpc<Channel>,
+}
+
+impl QueryClient {
+    pub fn new(channel: Channel) -> Self {
+        QueryClient {
+            inner: Grpc::new(channel),
+        }
+    }
+
+    pub async fn proposals(
+        &mut self,
+        request: QueryProposalsRequest,
+    ) -> Result<Response<QueryProposalsResponse>, Status> {
+        self.unary("/cosmos.gov.v1.Query/Proposals", request).await
+    }
+
+    pub async fn votes(
+        &mut self,
+        request: QueryVotesRequest,
+    ) -> Result<Response<QueryVotesResponse>, Status> {
+        self.unary("/cosmos.gov.v1.Query/Votes", request).await
+    }
+
+    pub async fn tally_result(
+        &mut self,
+        request: QueryTallyResultRequest,
+    ) -> Result<Response<QueryTallyResultResponse>, Status> {
+        self.unary("/cosmos.gov.v1.Query/TallyResult", request)
+            .await
+    }
+
+    async fn unary<Req, Resp>(
+        &mut self,
+        path: &'static str,
+        request: Req,
+    ) -> Result<Response<Resp>, Status>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Resp: prost::Message + Default + Send + Sync + 'static,
+    {
+        self.inner
+            .ready()
+            .await
+            .map_err(|e| Status::new(Code::Unknown, format!("Service was not ready: {e}")))?;
+        self.inner
+            .unary(
+                Request::new(request),
+                PathAndQuery::from_static(path),
+                ProstCodec::default(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,620 @@
+//! Governance proposal and validator voting participation statistics. Votes are removed from chain state once
+//! a proposal is tallied, so participation can only be computed from votes we recorded while each proposal was
+//! in its voting period. These records are persisted so they survive restarts
+
+use crate::{
+    gov_v1,
+    history::{load_state, save_state},
+    staking::{invalid_integer, ratio},
+    total_suppy::ALTHEA_DENOM,
+    ALTHEA_NODE_GRPC, ALTHEA_PREFIX,
+};
+use cosmos_sdk_proto_althea::cosmos::base::query::v1beta1::PageRequest;
+use cosmos_sdk_proto_althea::cosmos::base::v1beta1::Coin as ProtoCoin;
+use cosmos_sdk_proto_althea::cosmos::gov::v1beta1::query_client::QueryClient as GovQueryClient;
+use cosmos_sdk_proto_althea::cosmos::gov::v1beta1::{
+    Proposal, ProposalStatus, QueryProposalsRequest, QueryTallyResultRequest, QueryVotesRequest,
+    TallyResult, TextProposal,
+};
+use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::query_client::QueryClient as StakingQueryClient;
+use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::{
+    BondStatus, QueryPoolRequest, QueryValidatorsRequest,
+};
+use deep_space::client::PAGE;
+use deep_space::error::CosmosGrpcError;
+use deep_space::Address;
+use log::{error, info};
+use num256::Uint256;
+use prost::Message;
+use prost_types::{Any, Timestamp};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::sleep;
+use tonic::transport::{Channel, Endpoint};
+use tonic::Code;
+
+// update every 5 minutes, votes cast between the last update and the end of a proposal's voting period are missed
+const LOOP_TIME: Duration = Duration::from_secs(300);
+/// The name of the state file vote records are stored in
+const VOTE_RECORD_STATE: &str = "governance_votes";
+
+/// The type url of the v1 message wrapping a legacy proposal's content
+const MSG_EXEC_LEGACY_CONTENT: &str = "/cosmos.gov.v1.MsgExecLegacyContent";
+/// How many proposals are requested at once
+const PROPOSALS_PAGE_SIZE: u64 = 100;
+
+/// Proposal id -> addresses which voted on that proposal
+pub type VoteRecord = BTreeMap<u64, BTreeSet<String>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProposalInfo {
+    pub proposal_id: u64,
+    pub title: String,
+    /// One of PROPOSAL_STATUS_DEPOSIT_PERIOD, PROPOSAL_STATUS_VOTING_PERIOD, PROPOSAL_STATUS_PASSED,
+    /// PROPOSAL_STATUS_REJECTED or PROPOSAL_STATUS_FAILED
+    pub status: String,
+    /// The current tally for proposals in their voting period, the final tally otherwise
+    pub tally: Tally,
+    /// The total tallied voting power as a share of currently bonded tokens
+    pub turnout: f64,
+    /// The total deposit in the native denom
+    pub total_deposit: Uint256,
+    /// Unix timestamp in seconds, null if the proposal never entered its voting period
+    pub voting_end_time: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Tally {
+    pub yes: Uint256,
+    pub no: Uint256,
+    pub abstain: Uint256,
+    pub no_with_veto: Uint256,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidatorParticipation {
+    pub operator_address: String,
+    pub moniker: String,
+    /// How many of the considered proposals this validator voted on
+    pub proposals_voted: usize,
+    /// How many proposals participation was computed over, this may be less than requested if we don't
+    /// have vote records for older proposals
+    pub proposals_considered: usize,
+    pub participation_rate: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct GovValidator {
+    pub operator_address: String,
+    /// The account address this validator votes with
+    pub account_address: String,
+    pub moniker: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct GovernanceInfo {
+    /// Every proposal sorted by id, newest first
+    pub proposals: Vec<ProposalInfo>,
+    /// Validators in the active set
+    pub validators: Vec<GovValidator>,
+    pub votes: VoteRecord,
+}
+
+lazy_static! {
+    static ref GOVERNANCE_INFO: Arc<RwLock<Option<GovernanceInfo>>> = Arc::new(RwLock::new(None));
+}
+
+fn set_governance_info(input: GovernanceInfo) {
+    let mut r = GOVERNANCE_INFO.write().unwrap();
+    *r = Some(input);
+}
+
+pub fn get_governance_info() -> Option<GovernanceInfo> {
+    GOVERNANCE_INFO.read().unwrap().clone()
+}
+
+pub async fn governance_worker() {
+    loop {
+        let votes: VoteRecord = load_state(VOTE_RECORD_STATE).unwrap_or_default();
+        match compute_governance_info(ALTHEA_NODE_GRPC, votes).await {
+            Ok(v) => {
+                info!("Successfully updated governance info!");
+                if let Err(e) = save_state(VOTE_RECORD_STATE, &v.votes) {
+                    error!("Failed to save governance vote records with {:?}", e);
+                }
+                set_governance_info(v);
+            }
+            Err(e) => error!("Failed to update governance info with {:?}", e),
+        }
+        sleep(LOOP_TIME).await;
+    }
+}
+
+/// Queries every proposal and the active validator set, updating the vote record with votes on proposals
+/// that are currently in their voting period
+pub async fn compute_governance_info(
+    grpc: &str,
+    mut votes: VoteRecord,
+) -> Result<GovernanceInfo, CosmosGrpcError> {
+    let mut stakingrpc = StakingQueryClient::connect(grpc.to_string()).await?;
+
+    let pool = stakingrpc
+        .pool(QueryPoolRequest {})
+        .await?
+        .into_inner()
+        .pool
+        .ok_or_else(|| CosmosGrpcError::BadResponse("No staking pool".to_string()))?;
+    let bonded_tokens: Uint256 = pool
+        .bonded_tokens
+        .parse()
+        .map_err(|_| invalid_integer(&pool.bonded_tokens))?;
+
+    let mut validators = Vec::new();
+    for v in stakingrpc
+        .validators(QueryValidatorsRequest {
+            status: BondStatus::Bonded.as_str_name().to_string(),
+            pagination: PAGE,
+        })
+        .await?
+        .into_inner()
+        .validators
+    {
+        let account_address = Address::from_bech32(v.operator_address.clone())
+            .and_then(|a| a.to_bech32(ALTHEA_PREFIX))
+            .map_err(|e| CosmosGrpcError::BadResponse(format!("{e:?}")))?;
+        validators.push(GovValidator {
+            operator_address: v.operator_address,
+            account_address,
+            moniker: v.description.map(|d| d.moniker).unwrap_or_default(),
+        });
+    }
+
+    let (mut govrpc, proposals) = GovClient::connect(grpc, ProposalStatus::Unspecified).await?;
+
+    let mut proposal_info = Vec::new();
+    for proposal in proposals {
+        let tally = if proposal.status == ProposalStatus::VotingPeriod as i32 {
+            let voters = govrpc.voters(proposal.id).await?;
+            votes.entry(proposal.id).or_default().extend(voters);
+            govrpc.tally(proposal.id).await?
+        } else {
+            proposal.final_tally.clone()
+        };
+        proposal_info.push(get_proposal_info(proposal, tally, bonded_tokens)?);
+    }
+    proposal_info.sort_by_key(|p| std::cmp::Reverse(p.proposal_id));
+
+    Ok(GovernanceInfo {
+        proposals: proposal_info,
+        validators,
+        votes,
+    })
+}
+
+/// A proposal from either version of the gov module, their statuses have the same values
+#[derive(Debug, Clone)]
+pub struct GovProposal {
+    pub id: u64,
+    pub title: String,
+    pub status: i32,
+    /// The legacy content of a v1beta1 proposal, or the messages of a v1 proposal with any legacy content
+    /// unwrapped from its MsgExecLegacyContent
+    pub messages: Vec<Any>,
+    pub final_tally: Option<Tally>,
+    pub total_deposit: Vec<ProtoCoin>,
+    pub voting_end_time: Option<Timestamp>,
+}
+
+impl TryFrom<Proposal> for GovProposal {
+    type Error = CosmosGrpcError;
+
+    fn try_from(proposal: Proposal) -> Result<Self, Self::Error> {
+        Ok(GovProposal {
+            id: proposal.proposal_id,
+            title: proposal
+                .content
+                .as_ref()
+                .map(legacy_content_title)
+                .unwrap_or_default(),
+            status: proposal.status,
+            messages: proposal.content.into_iter().collect(),
+            final_tally: proposal
+                .final_tally_result
+                .map(Tally::try_from)
+                .transpose()?,
+            total_deposit: proposal.total_deposit,
+            voting_end_time: proposal.voting_end_time,
+        })
+    }
+}
+
+impl TryFrom<gov_v1::Proposal> for GovProposal {
+    type Error = CosmosGrpcError;
+
+    fn try_from(proposal: gov_v1::Proposal) -> Result<Self, Self::Error> {
+        Ok(GovProposal {
+            id: proposal.id,
+            title: v1_proposal_title(&proposal),
+            status: proposal.status,
+            messages: proposal
+                .messages
+                .into_iter()
+                .map(|m| legacy_content(&m).unwrap_or(m))
+                .collect(),
+            final_tally: proposal
+                .final_tally_result
+                .map(Tally::try_from)
+                .transpose()?,
+            total_deposit: proposal.total_deposit,
+            voting_end_time: proposal.voting_end_time,
+        })
+    }
+}
+
+// errors are propagated with ? through the TryFrom impls below, so this returns the same error type
+#[allow(clippy::result_large_err)]
+fn parse_tally_amount(v: &str) -> Result<Uint256, CosmosGrpcError> {
+    v.parse().map_err(|_| invalid_integer(v))
+}
+
+impl TryFrom<TallyResult> for Tally {
+    type Error = CosmosGrpcError;
+
+    fn try_from(t: TallyResult) -> Result<Self, Self::Error> {
+        Ok(Tally {
+            yes: parse_tally_amount(&t.yes)?,
+            no: parse_tally_amount(&t.no)?,
+            abstain: parse_tally_amount(&t.abstain)?,
+            no_with_veto: parse_tally_amount(&t.no_with_veto)?,
+        })
+    }
+}
+
+impl TryFrom<gov_v1::TallyResult> for Tally {
+    type Error = CosmosGrpcError;
+
+    fn try_from(t: gov_v1::TallyResult) -> Result<Self, Self::Error> {
+        Ok(Tally {
+            yes: parse_tally_amount(&t.yes_count)?,
+            no: parse_tally_amount(&t.no_count)?,
+            abstain: parse_tally_amount(&t.abstain_count)?,
+            no_with_veto: parse_tally_amount(&t.no_with_veto_count)?,
+        })
+    }
+}
+
+/// every legacy proposal content type starts with a title and description, so any of them
+/// can be decoded as a text proposal to get the title
+fn legacy_content_title(content: &Any) -> String {
+    TextProposal::decode(content.value.as_slice())
+        .map(|c| c.title)
+        .unwrap_or_default()
+}
+
+/// The legacy proposal content a v1 message wraps, None for any other message
+fn legacy_content(message: &Any) -> Option<Any> {
+    if message.type_url != MSG_EXEC_LEGACY_CONTENT {
+        return None;
+    }
+    gov_v1::MsgExecLegacyContent::decode(message.value.as_slice())
+        .ok()?
+        .content
+}
+
+/// v1 proposals only have a title field since Cosmos SDK 0.47, before that legacy proposals carry one in their
+/// content and others usually have json metadata with a title
+fn v1_proposal_title(proposal: &gov_v1::Proposal) -> String {
+    if !proposal.title.is_empty() {
+        return proposal.title.clone();
+    }
+    match proposal.messages.iter().find_map(legacy_content) {
+        Some(content) => legacy_content_title(&content),
+        None => serde_json::from_str::<serde_json::Value>(&proposal.metadata)
+            .ok()
+            .and_then(|m| m.get("title")?.as_str().map(str::to_string))
+            .unwrap_or_default(),
+    }
+}
+
+/// The gov module's query service, v1 where the chain supports it and v1beta1 otherwise. v1beta1 can't return
+/// proposals which aren't legacy proposals
+pub enum GovClient {
+    V1(gov_v1::QueryClient),
+    V1Beta1(GovQueryClient<Channel>),
+}
+
+impl GovClient {
+    /// Connects with the newest gov version the node serves, returning every proposal with `status`,
+    /// Unspecified for every proposal
+    pub async fn connect(
+        grpc: &str,
+        status: ProposalStatus,
+    ) -> Result<(Self, Vec<GovProposal>), CosmosGrpcError> {
+        let channel = Endpoint::new(grpc.to_string())?.connect().await?;
+        let mut client = GovClient::V1(gov_v1::QueryClient::new(channel.clone()));
+        match client.proposals(status).await {
+            Ok(v) => Ok((client, v)),
+            Err(CosmosGrpcError::RequestError { error }) if error.code() == Code::Unimplemented => {
+                info!("gov v1 is not available, falling back to v1beta1");
+                let mut client = GovClient::V1Beta1(GovQueryClient::new(channel));
+                let proposals = client.proposals(status).await?;
+                Ok((client, proposals))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Every proposal with `status`, following the pagination to the last page
+    pub async fn proposals(
+        &mut self,
+        status: ProposalStatus,
+    ) -> Result<Vec<GovProposal>, CosmosGrpcError> {
+        let mut proposals = Vec::new();
+        let mut key = Vec::new();
+        loop {
+            let pagination = Some(PageRequest {
+                key,
+                offset: 0,
+                limit: PROPOSALS_PAGE_SIZE,
+                count_total: false,
+                reverse: false,
+            });
+            let page = match self {
+                GovClient::V1(c) => {
+                    let res = c
+                        .proposals(gov_v1::QueryProposalsRequest {
+                            proposal_status: status as i32,
+                            voter: String::new(),
+                            depositor: String::new(),
+                            pagination,
+                        })
+                        .await?
+                        .into_inner();
+                    for proposal in res.proposals {
+                        proposals.push(GovProposal::try_from(proposal)?);
+                    }
+                    res.pagination
+                }
+                GovClient::V1Beta1(c) => {
+                    let res = c
+                        .proposals(QueryProposalsRequest {
+                            proposal_status: status as i32,
+                            voter: String::new(),
+                            depositor: String::new(),
+                            pagination,
+                        })
+                        .await?
+                        .into_inner();
+                    for proposal in res.proposals {
+                        proposals.push(GovProposal::try_from(proposal)?);
+                    }
+                    res.pagination
+                }
+            };
+            key = match page {
+                Some(p) if !p.next_key.is_empty() => p.next_key,
+                _ => return Ok(proposals),
+            };
+        }
+    }
+
+    /// The addresses which have voted on a proposal in its voting period
+    async fn voters(&mut self, proposal_id: u64) -> Result<Vec<String>, CosmosGrpcError> {
+        Ok(match self {
+            GovClient::V1(c) => c
+                .votes(gov_v1::QueryVotesRequest {
+                    proposal_id,
+                    pagination: PAGE,
+                })
+                .await?
+                .into_inner()
+                .votes
+                .into_iter()
+                .map(|v| v.voter)
+                .collect(),
+            GovClient::V1Beta1(c) => c
+                .votes(QueryVotesRequest {
+                    proposal_id,
+                    pagination: PAGE,
+                })
+                .await?
+                .into_inner()
+                .votes
+                .into_iter()
+                .map(|v| v.voter)
+                .collect(),
+        })
+    }
+
+    /// The current tally of a proposal in its voting period
+    async fn tally(&mut self, proposal_id: u64) -> Result<Option<Tally>, CosmosGrpcError> {
+        Ok(match self {
+            GovClient::V1(c) => c
+                .tally_result(gov_v1::QueryTallyResultRequest { proposal_id })
+                .await?
+                .into_inner()
+                .tally
+                .map(Tally::try_from)
+                .transpose()?,
+            GovClient::V1Beta1(c) => c
+                .tally_result(QueryTallyResultRequest { proposal_id })
+                .await?
+                .into_inner()
+                .tally
+                .map(Tally::try_from)
+                .transpose()?,
+        })
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn get_proposal_info(
+    proposal: GovProposal,
+    tally: Option<Tally>,
+    bonded_tokens: Uint256,
+) -> Result<ProposalInfo, CosmosGrpcError> {
+    let tally = tally.unwrap_or(Tally {
+        yes: 0u8.into(),
+        no: 0u8.into(),
+        abstain: 0u8.into(),
+        no_with_veto: 0u8.into(),
+    });
+    let total_voted = tally.yes + tally.no + tally.abstain + tally.no_with_veto;
+
+    let mut total_deposit: Uint256 = 0u8.into();
+    for coin in proposal.total_deposit {
+        if coin.denom == ALTHEA_DENOM {
+            total_deposit += parse_tally_amount(&coin.amount)?;
+        }
+    }
+
+    let status = ProposalStatus::try_from(proposal.status).unwrap_or(ProposalStatus::Unspecified);
+    Ok(ProposalInfo {
+        proposal_id: proposal.id,
+        title: proposal.title,
+        status: status.as_str_name().to_string(),
+        tally,
+        turnout: ratio(total_voted, bonded_tokens),
+        total_deposit,
+        // proposals which never reached the voting period have a zero timestamp
+        voting_end_time: proposal
+            .voting_end_time
+            .map(|t| t.seconds)
+            .filter(|s| *s > 0),
+    })
+}
+
+/// Computes each active validator's voting participation over the most recent `last_n` proposals which
+/// reached their voting period and for which we have vote records
+pub fn compute_participation(info: &GovernanceInfo, last_n: usize) -> Vec<ValidatorParticipation> {
+    let considered: Vec<&BTreeSet<String>> = info
+        .proposals
+        .iter()
+        .filter_map(|p| info.votes.get(&p.proposal_id))
+        .take(last_n)
+        .collect();
+
+    let mut result: Vec<ValidatorParticipation> = info
+        .validators
+        .iter()
+        .map(|v| {
+            let proposals_voted = considered
+                .iter()
+                .filter(|voters| voters.contains(&v.account_address))
+                .count();
+            ValidatorParticipation {
+                operator_address: v.operator_address.clone(),
+                moniker: v.moniker.clone(),
+                proposals_voted,
+                proposals_considered: considered.len(),
+                participation_rate: if considered.is_empty() {
+                    0.0
+                } else {
+                    proposals_voted as f64 / considered.len() as f64
+                },
+            }
+        })
+        .collect();
+    result.sort_by(|a, b| b.participation_rate.total_cmp(&a.participation_rate));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_v1_proposal_title() {
+        let text = TextProposal {
+            title: "Legacy".to_string(),
+            description: String::new(),
+        };
+        let legacy = gov_v1::MsgExecLegacyContent {
+            content: Some(Any {
+                type_url: "/cosmos.gov.v1beta1.TextProposal".to_string(),
+                value: text.encode_to_vec(),
+            }),
+            authority: String::new(),
+        };
+        let mut proposal = gov_v1::Proposal {
+            messages: vec![Any {
+                type_url: MSG_EXEC_LEGACY_CONTENT.to_string(),
+                value: legacy.encode_to_vec(),
+            }],
+            metadata: r#"{"title": "From metadata"}"#.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(v1_proposal_title(&proposal), "Legacy");
+        proposal.messages.clear();
+        assert_eq!(v1_proposal_title(&proposal), "From metadata");
+        proposal.title = "Title".to_string();
+        assert_eq!(v1_proposal_title(&proposal), "Title");
+        // metadata is often an ipfs link
+        proposal.title.clear();
+        proposal.metadata = "ipfs://abc".to_string();
+        assert_eq!(v1_proposal_title(&proposal), "");
+
+        let mut result = gov_v1::TallyResult {
+            yes_count: "10".to_string(),
+            abstain_count: "1".to_string(),
+            no_count: "2".to_string(),
+            no_with_veto_count: "0".to_string(),
+        };
+        let tally = Tally::try_from(result.clone()).unwrap();
+        assert_eq!(tally.yes, 10u8.into());
+        assert_eq!(tally.no, 2u8.into());
+        assert_eq!(tally.no_with_veto, 0u8.into());
+        // a malformed count is an error rather than a zero
+        result.no_with_veto_count = String::new();
+        assert!(Tally::try_from(result.clone()).is_err());
+        result.no_with_veto_count = "1.5".to_string();
+        assert!(Tally::try_from(result).is_err());
+    }
+
+    #[test]
+    fn test_compute_participation() {
+        let proposal = |id: u64| ProposalInfo {
+            proposal_id: id,
+            title: String::new(),
+            status: String::new(),
+            tally: Tally {
+                yes: 0u8.into(),
+                no: 0u8.into(),
+                abstain: 0u8.into(),
+                no_with_veto: 0u8.into(),
+            },
+            turnout: 0.0,
+            total_deposit: 0u8.into(),
+            voting_end_time: None,
+        };
+        let validator = |name: &str| GovValidator {
+            operator_address: format!("{name}valoper"),
+            account_address: name.to_string(),
+            moniker: name.to_string(),
+        };
+        let voters = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<BTreeSet<_>>();
+
+        let mut votes = VoteRecord::new();
+        votes.insert(3, voters(&["a", "b"]));
+        votes.insert(2, voters(&["a"]));
+        // proposal 1 has no vote record and is never considered
+        let info = GovernanceInfo {
+            proposals: vec![proposal(3), proposal(2), proposal(1)],
+            validators: vec![validator("a"), validator("b"), validator("c")],
+            votes,
+        };
+
+        let all = compute_participation(&info, 10);
+        assert_eq!(all[0].moniker, "a");
+        assert_eq!(all[0].proposals_voted, 2);
+        assert_eq!(all[0].proposals_considered, 2);
+        assert_eq!(all[1].participation_rate, 0.5);
+        assert_eq!(all[2].participation_rate, 0.0);
+
+        let last = compute_participation(&info, 1);
+        assert_eq!(last[0].proposals_considered, 1);
+        assert_eq!(last[1].participation_rate, 1.0);
+    }
+}
@@ -0,0 +1,190 @@
+//! Minimal on disk storage for periodic snapshots. Each kind of snapshot is stored as a newline delimited
+//! json file in the data directory so that historical values survive restarts without needing a database.
+//! State that is updated in place rather than appended to, such as indexer progress, is stored as a single
+//! json file per name
+
+use crate::DATA_DIR;
+use log::error;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fs::{create_dir_all, rename, write, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SECONDS_PER_DAY: u64 = 86400;
+/// How many bytes from the end of a history file are read first when looking for the last snapshot
+const TAIL_WINDOW: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot<T> {
+    /// Unix timestamp in seconds of when this snapshot was taken
+    pub timestamp: u64,
+    pub data: T,
+}
+
+/// Returns the current unix timestamp in seconds
+pub fn get_current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Returns the unix timestamp of the start of the UTC day containing `timestamp`
+pub fn day_start(timestamp: u64) -> u64 {
+    timestamp - timestamp % SECONDS_PER_DAY
+}
+
+fn snapshot_path(name: &str) -> PathBuf {
+    PathBuf::from(DATA_DIR).join(format!("{name}.jsonl"))
+}
+
+/// Appends a snapshot to the history file with the given name
+pub fn save_snapshot<T: Serialize>(name: &str, snapshot: &Snapshot<T>) -> std::io::Result<()> {
+    create_dir_all(DATA_DIR)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(snapshot_path(name))?;
+    let line = serde_json::to_string(snapshot)?;
+    writeln!(file, "{line}")
+}
+
+/// Loads every snapshot from the history file with the given name in the order they where saved,
+/// returns an empty list if no snapshots have been saved yet. Lines that fail to parse are skipped
+pub fn load_snapshots<T: DeserializeOwned>(name: &str) -> Vec<Snapshot<T>> {
+    let file = match File::open(snapshot_path(name)) {
+        Ok(f) => f,
+        Err(_) => return Vec::new(),
+    };
+    let mut ret = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                error!("Failed to read {} history with {:?}", name, e);
+                break;
+            }
+        };
+        match serde_json::from_str(&line) {
+            Ok(v) => ret.push(v),
+            Err(e) => error!("Skipping invalid {} snapshot with {:?}", name, e),
+        }
+    }
+    ret
+}
+
+/// Loads the most recently saved snapshot from the history file with the given name without parsing the rest of
+/// the history, returns None if no snapshots have been saved yet. Lines that fail to parse are skipped
+pub fn load_last_snapshot<T: DeserializeOwned>(name: &str) -> Option<Snapshot<T>> {
+    let file = File::open(snapshot_path(name)).ok()?;
+    let len = file.metadata().ok()?.len();
+    match last_snapshot(name, file, len, TAIL_WINDOW) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to read {} history with {:?}", name, e);
+            None
+        }
+    }
+}
+
+/// Reads `window` bytes from the end of the history, doubling it until a line in it parses or the whole
+/// history has been read
+fn last_snapshot<T: DeserializeOwned>(
+    name: &str,
+    mut history: impl Read + Seek,
+    len: u64,
+    mut window: u64,
+) -> std::io::Result<Option<Snapshot<T>>> {
+    loop {
+        window = window.min(len);
+        history.seek(SeekFrom::Start(len - window))?;
+        let mut tail = Vec::new();
+        (&mut history).take(window).read_to_end(&mut tail)?;
+        // the first line may have been cut off unless the window reaches the start of the history
+        let lines = match tail.iter().position(|b| *b == b'\n') {
+            _ if window == len => &tail[..],
+            Some(i) => &tail[i + 1..],
+            None => &[],
+        };
+        for line in lines.split(|b| *b == b'\n').rev() {
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_slice(line) {
+                Ok(v) => return Ok(Some(v)),
+                Err(e) => error!("Skipping invalid {} snapshot with {:?}", name, e),
+            }
+        }
+        if window == len {
+            return Ok(None);
+        }
+        window *= 2;
+    }
+}
+
+fn state_path(name: &str) -> PathBuf {
+    PathBuf::from(DATA_DIR).join(format!("{name}.json"))
+}
+
+/// Replaces the state file with the given name, the file is written to a temporary path and then moved
+/// into place so that a crash can't leave a partially written state behind
+pub fn save_state<T: Serialize>(name: &str, state: &T) -> std::io::Result<()> {
+    create_dir_all(DATA_DIR)?;
+    let path = state_path(name);
+    let tmp_path = path.with_extension("json.tmp");
+    write(&tmp_path, serde_json::to_vec(state)?)?;
+    rename(tmp_path, path)
+}
+
+/// Loads the state file with the given name, returns None if it has never been saved or can't be parsed
+pub fn load_state<T: DeserializeOwned>(name: &str) -> Option<T> {
+    let file = File::open(state_path(name)).ok()?;
+    match serde_json::from_reader(BufReader::new(file)) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            error!("Failed to parse {} state with {:?}", name, e);
+            None
+        }
+    }
+}
+
+/// Finds the most recent snapshot taken at or before the given timestamp, snapshots are assumed to be
+/// in the order they where saved
+pub fn find_snapshot_at<T>(snapshots: &[Snapshot<T>], timestamp: u64) -> Option<&Snapshot<T>> {
+    snapshots.iter().rev().find(|s| s.timestamp <= timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_last_snapshot() {
+        let history = (1..=50u64)
+            .map(|i| {
+                format!(
+                    "{{\"timestamp\":{i},\"data\":\"{}\"}}\n",
+                    "x".repeat(i as usize)
+                )
+            })
+            .collect::<String>()
+            + "not json\n";
+        let len = history.len() as u64;
+        // windows too small for a whole line are grown until one fits
+        for window in [1, 10, 100, len] {
+            let last: Snapshot<String> = last_snapshot("test", Cursor::new(&history), len, window)
+                .unwrap()
+                .unwrap();
+            assert_eq!(last.timestamp, 50);
+        }
+
+        let single = "{\"timestamp\":1,\"data\":0}";
+        let last: Option<Snapshot<u64>> =
+            last_snapshot("test", Cursor::new(single), single.len() as u64, 4).unwrap();
+        assert_eq!(last.unwrap().timestamp, 1);
+        let empty: Option<Snapshot<u64>> = last_snapshot("test", Cursor::new(""), 0, 4).unwrap();
+        assert!(empty.is_none());
+    }
+}
@@ -0,0 +1,130 @@
+//! Caching headers for endpoints serving periodically updated data, so that responses can be cached by a CDN and
+//! clients can revalidate with conditional requests instead of downloading the same data again
+
+use crate::settings::get_settings;
+use actix_web::http::header::{
+    CacheControl, CacheDirective, ContentType, ETag, EntityTag, HttpDate, IfModifiedSince,
+    IfNoneMatch, LastModified,
+};
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use log::error;
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Serializes `data` with Cache-Control, Last-Modified and ETag headers, or returns 304 Not Modified if the
+/// request's conditional headers show the client already has it. `last_modified` is the unix timestamp of when
+/// the data was computed
+pub fn cached_json<T: Serialize>(req: &HttpRequest, data: &T, last_modified: u64) -> HttpResponse {
+    let body = match serde_json::to_string(data) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Failed to serialize response with {:?}", e);
+            return HttpResponse::InternalServerError().json("Failed to serialize response");
+        }
+    };
+    // weak because the compression middleware may change the bytes sent
+    let etag = EntityTag::new_weak(sha256::digest(body.as_str()));
+    let last_modified = UNIX_EPOCH + Duration::from_secs(last_modified);
+    let cache_control = CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(get_settings().cache_max_age),
+    ]);
+
+    let not_modified = is_not_modified(
+        req.get_header::<IfNoneMatch>(),
+        req.get_header::<IfModifiedSince>(),
+        &etag,
+        last_modified,
+    );
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(ETag(etag))
+        .insert_header(LastModified(HttpDate::from(last_modified)))
+        .insert_header(cache_control);
+    if not_modified {
+        response.finish()
+    } else {
+        response.content_type(ContentType::json()).body(body)
+    }
+}
+
+/// If-Modified-Since is ignored when If-None-Match is sent, as it is the more precise of the two
+fn is_not_modified(
+    if_none_match: Option<IfNoneMatch>,
+    if_modified_since: Option<IfModifiedSince>,
+    etag: &EntityTag,
+    last_modified: SystemTime,
+) -> bool {
+    match (if_none_match, if_modified_since) {
+        (Some(IfNoneMatch::Any), _) => true,
+        (Some(IfNoneMatch::Items(tags)), _) => tags.iter().any(|t| t.weak_eq(etag)),
+        (None, Some(IfModifiedSince(since))) => last_modified <= SystemTime::from(since),
+        (None, None) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_not_modified() {
+        let etag = EntityTag::new_weak("abc".to_string());
+        let other = EntityTag::new_strong("def".to_string());
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let before = IfModifiedSince(HttpDate::from(modified - Duration::from_secs(1)));
+        let at = IfModifiedSince(HttpDate::from(modified));
+
+        assert!(!is_not_modified(None, None, &etag, modified));
+        assert!(is_not_modified(None, Some(at.clone()), &etag, modified));
+        assert!(!is_not_modified(None, Some(before), &etag, modified));
+        assert!(is_not_modified(
+            Some(IfNoneMatch::Any),
+            None,
+            &etag,
+            modified
+        ));
+        // the strong form of the same tag matches a weak comparison
+        assert!(is_not_modified(
+            Some(IfNoneMatch::Items(vec![
+                other.clone(),
+                EntityTag::new_strong("abc".to_string())
+            ])),
+            None,
+            &etag,
+            modified
+        ));
+        // a changed etag wins over an unchanged date
+        assert!(!is_not_modified(
+            Some(IfNoneMatch::Items(vec![other])),
+            Some(at),
+            &etag,
+            modified
+        ));
+    }
+
+    #[test]
+    fn test_cached_json() {
+        use actix_web::http::{header, StatusCode};
+        use actix_web::test::TestRequest;
+
+        let response = cached_json(&TestRequest::default().to_http_request(), &vec![1, 2], 100);
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers().get(header::ETAG).unwrap().clone();
+        assert!(response.headers().contains_key(header::LAST_MODIFIED));
+        assert!(response.headers().contains_key(header::CACHE_CONTROL));
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, etag.clone()))
+            .to_http_request();
+        let response = cached_json(&req, &vec![1, 2], 100);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(header::ETAG), Some(&etag));
+        let response = cached_json(&req, &vec![1, 2, 3], 100);
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,152 @@
+//! Resolves the origin of ibc/ denoms in the total supply using the IBC transfer module denom traces, this lets
+//! us report IBC assets which are not yet in the token registry in config.rs instead of silently dropping them
+
+use cosmos_sdk_proto_althea::cosmos::bank::v1beta1::{
+    query_client::QueryClient as BankQueryClient, QueryDenomMetadataRequest,
+};
+use cosmos_sdk_proto_althea::ibc::applications::transfer::v1::{
+    query_client::QueryClient as IbcTransferQueryClient, DenomTrace, QueryDenomTraceRequest,
+    QueryDenomTracesRequest,
+};
+use deep_space::client::PAGE;
+use num256::Uint256;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{config::get_token, tvl::TotalSupply};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnregisteredAsset {
+    /// The full ibc/ denom of this asset on Althea L1
+    pub denom: String,
+    /// The denom of this asset on its origin chain
+    pub base_denom: String,
+    /// The chain of port/channel identifiers this asset took to reach Althea L1, eg transfer/channel-0
+    pub path: String,
+    /// The total supply of this asset on Althea L1
+    pub supply: Uint256,
+    /// Display information from the bank module, may be null if no metadata has been registered
+    pub metadata: Option<AssetMetadata>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetMetadata {
+    pub name: String,
+    pub symbol: String,
+    /// The denom unit used for display
+    pub display: String,
+    /// The exponent of the display denom unit, may be null if the display unit is not listed
+    pub decimals: Option<u32>,
+}
+
+/// Computes the ibc/ denom for a denom trace, this is the uppercase hex sha256 hash of the full trace path
+pub fn ibc_denom_from_trace(trace: &DenomTrace) -> String {
+    let full_path = if trace.path.is_empty() {
+        trace.base_denom.clone()
+    } else {
+        format!("{}/{}", trace.path, trace.base_denom)
+    };
+    format!("ibc/{}", sha256::digest(full_path).to_uppercase())
+}
+
+/// Finds every ibc/ denom in the total supply which is not in the token registry and resolves its origin
+pub async fn get_unregistered_assets(
+    supply: &TotalSupply,
+    grpc: &str,
+) -> Result<Vec<UnregisteredAsset>, String> {
+    let mut ibc_client = IbcTransferQueryClient::connect(grpc.to_string())
+        .await
+        .map_err(|e| format!("Failed to connect to IBC transfer gRPC endpoint: {e}"))?;
+    let mut bank_client = BankQueryClient::connect(grpc.to_string())
+        .await
+        .map_err(|e| format!("Failed to connect to bank gRPC endpoint: {e}"))?;
+
+    let traces: HashMap<String, DenomTrace> = ibc_client
+        .denom_traces(QueryDenomTracesRequest { pagination: PAGE })
+        .await
+        .map_err(|e| format!("Failed to query denom traces: {e}"))?
+        .into_inner()
+        .denom_traces
+        .into_iter()
+        .map(|t| (ibc_denom_from_trace(&t), t))
+        .collect();
+
+    let mut result = Vec::new();
+    for coin in supply {
+        if !coin.denom.starts_with("ibc/") || get_token(&coin.denom).is_some() {
+            continue;
+        }
+        let trace = match traces.get(&coin.denom) {
+            Some(t) => t.clone(),
+            // a trace may have been added after our bulk query, look it up directly
+            None => ibc_client
+                .denom_trace(QueryDenomTraceRequest {
+                    hash: coin.denom.clone(),
+                })
+                .await
+                .map_err(|e| format!("Failed to query denom trace for {}: {e}", coin.denom))?
+                .into_inner()
+                .denom_trace
+                .ok_or(format!("No denom trace for {}", coin.denom))?,
+        };
+        let supply: Uint256 = coin.amount.parse().map_err(|e| {
+            format!(
+                "Failed to parse supply amount '{}' for denom {}: {e}",
+                coin.amount, coin.denom
+            )
+        })?;
+        let metadata = get_asset_metadata(&mut bank_client, &coin.denom).await;
+
+        result.push(UnregisteredAsset {
+            denom: coin.denom.clone(),
+            base_denom: trace.base_denom,
+            path: trace.path,
+            supply,
+            metadata,
+        });
+    }
+    Ok(result)
+}
+
+/// Gets the bank module metadata for a denom, returns None if no metadata is registered
+async fn get_asset_metadata(
+    bank_client: &mut BankQueryClient<tonic::transport::Channel>,
+    denom: &str,
+) -> Option<AssetMetadata> {
+    let metadata = bank_client
+        .denom_metadata(QueryDenomMetadataRequest {
+            denom: denom.to_string(),
+        })
+        .await
+        .ok()?
+        .into_inner()
+        .metadata?;
+    let decimals = metadata
+        .denom_units
+        .iter()
+        .find(|u| u.denom == metadata.display)
+        .map(|u| u.exponent);
+    Some(AssetMetadata {
+        name: metadata.name,
+        symbol: metadata.symbol,
+        display: metadata.display,
+        decimals,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ibc_denom_from_trace() {
+        let trace = DenomTrace {
+            path: "transfer/channel-0".to_string(),
+            base_denom: "uatom".to_string(),
+        };
+        assert_eq!(
+            ibc_denom_from_trace(&trace),
+            "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2"
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! Inflation and estimated staking APR, computed from the mint module, the distribution module
+//! community tax, and the staking pool
+
+use crate::{
+    chains::ALTHEA_CHAIN_ID,
+    history::{get_current_timestamp, save_snapshot, Snapshot},
+    staking::{dec_to_f64, ratio},
+    total_suppy::{get_supply_info, DEC_ONE},
+    ALTHEA_NODE_GRPC,
+};
+use cosmos_sdk_proto_althea::cosmos::distribution::v1beta1::query_client::QueryClient as DistQueryClient;
+use cosmos_sdk_proto_althea::cosmos::distribution::v1beta1::QueryParamsRequest as DistQueryParamsRequest;
+use cosmos_sdk_proto_althea::cosmos::mint::v1beta1::query_client::QueryClient as MintQueryClient;
+use cosmos_sdk_proto_althea::cosmos::mint::v1beta1::{
+    QueryAnnualProvisionsRequest, QueryInflationRequest,
+    QueryParamsRequest as MintQueryParamsRequest,
+};
+use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::query_client::QueryClient as StakingQueryClient;
+use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::QueryPoolRequest;
+use deep_space::error::CosmosGrpcError;
+use log::{error, info};
+use num256::Uint256;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::sleep;
+
+// update once an hour, each update is stored as a snapshot
+const LOOP_TIME: Duration = Duration::from_secs(3600);
+/// How long to wait for the supply info to be generated before computing inflation
+const RETRY_TIME: Duration = Duration::from_secs(60);
+/// The name of the history file inflation snapshots are stored in
+pub const INFLATION_HISTORY: &str = "inflation";
+/// The name of the history file staking APR snapshots are stored in
+pub const STAKING_APR_HISTORY: &str = "staking_apr";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InflationInfo {
+    /// The current inflation rate as set by the mint module
+    pub mint_inflation: f64,
+    /// Tokens minted per year at the current rate in aalthea
+    pub annual_provisions: Uint256,
+    /// Tokens minted per year at the current rate in whole ALTHEA
+    pub annual_provisions_althea: f64,
+    /// The total supply from the supply info, in aalthea
+    pub total_supply: Uint256,
+    /// annual_provisions / total_supply, this is the actual rate of supply growth
+    pub inflation: f64,
+    pub inflation_min: f64,
+    pub inflation_max: f64,
+    pub inflation_rate_change: f64,
+    /// The bonded ratio the mint module adjusts inflation towards
+    pub goal_bonded: f64,
+    pub blocks_per_year: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StakingApr {
+    pub bonded_tokens: Uint256,
+    /// The fraction of inflation sent to the community pool instead of stakers
+    pub community_tax: f64,
+    /// annual_provisions * (1 - community_tax) / bonded_tokens, before validator commission
+    pub nominal_apr: f64,
+    /// The nominal APR adjusted for supply inflation, (1 + nominal_apr) / (1 + inflation) - 1
+    pub real_apr: f64,
+}
+
+lazy_static! {
+    static ref INFLATION_INFO: Arc<RwLock<Option<(InflationInfo, StakingApr)>>> =
+        Arc::new(RwLock::new(None));
+}
+
+fn set_inflation_info(input: (InflationInfo, StakingApr)) {
+    let mut r = INFLATION_INFO.write().unwrap();
+    *r = Some(input);
+}
+
+pub fn get_inflation_info() -> Option<InflationInfo> {
+    INFLATION_INFO.read().unwrap().clone().map(|v| v.0)
+}
+
+pub fn get_staking_apr() -> Option<StakingApr> {
+    INFLATION_INFO.read().unwrap().clone().map(|v| v.1)
+}
+
+pub async fn inflation_worker() {
+    loop {
+        // inflation is measured against the total supply from the supply info
+        let total_supply = match get_supply_info(ALTHEA_CHAIN_ID) {
+            Some(v) => v.total_supply,
+            None => {
+                sleep(RETRY_TIME).await;
+                continue;
+            }
+        };
+        match compute_inflation_info(ALTHEA_NODE_GRPC, total_supply).await {
+            Ok((inflation, apr)) => {
+                info!("Successfully updated inflation info!");
+                let timestamp = get_current_timestamp();
+                let res = save_snapshot(
+                    INFLATION_HISTORY,
+                    &Snapshot {
+                        timestamp,
+                        data: inflation.clone(),
+                    },
+                )
+                .and_then(|_| {
+                    save_snapshot(
+                        STAKING_APR_HISTORY,
+                        &Snapshot {
+                            timestamp,
+                            data: apr.clone(),
+                        },
+                    )
+                });
+                if let Err(e) = res {
+                    error!("Failed to save inflation snapshot with {:?}", e);
+                }
+                set_inflation_info((inflation, apr));
+            }
+            Err(e) => error!("Failed to update inflation info with {:?}", e),
+        }
+        sleep(LOOP_TIME).await;
+    }
+}
+
+pub async fn compute_inflation_info(
+    grpc: &str,
+    total_supply: Uint256,
+) -> Result<(InflationInfo, StakingApr), CosmosGrpcError> {
+    let mut mintrpc = MintQueryClient::connect(grpc.to_string()).await?;
+    let mut distrpc = DistQueryClient::connect(grpc.to_string()).await?;
+    let mut stakingrpc = StakingQueryClient::connect(grpc.to_string()).await?;
+
+    let mint_params = mintrpc
+        .params(MintQueryParamsRequest {})
+        .await?
+        .into_inner()
+        .params
+        .ok_or_else(|| CosmosGrpcError::BadResponse("No mint params".to_string()))?;
+    let mint_inflation = mintrpc
+        .inflation(QueryInflationRequest {})
+        .await?
+        .into_inner()
+        .inflation;
+    let mint_inflation = dec_to_f64(&String::from_utf8_lossy(&mint_inflation));
+    let annual_provisions = mintrpc
+        .annual_provisions(QueryAnnualProvisionsRequest {})
+        .await?
+        .into_inner()
+        .annual_provisions;
+    let annual_provisions = String::from_utf8_lossy(&annual_provisions).to_string();
+    let annual_provisions = dec_to_uint(&annual_provisions).ok_or_else(|| {
+        CosmosGrpcError::BadResponse(format!("Invalid annual provisions {annual_provisions}"))
+    })?;
+
+    let community_tax = distrpc
+        .params(DistQueryParamsRequest {})
+        .await?
+        .into_inner()
+        .params
+        .map(|p| dec_to_f64(&p.community_tax))
+        .unwrap_or(0.0);
+
+    let pool = stakingrpc
+        .pool(QueryPoolRequest {})
+        .await?
+        .into_inner()
+        .pool
+        .ok_or_else(|| CosmosGrpcError::BadResponse("No staking pool".to_string()))?;
+    let bonded_tokens: Uint256 = pool.bonded_tokens.parse().map_err(|_| {
+        CosmosGrpcError::BadResponse(format!("Invalid bonded tokens {}", pool.bonded_tokens))
+    })?;
+
+    let (inflation, apr) = compute_staking_apr(
+        annual_provisions,
+        total_supply,
+        bonded_tokens,
+        community_tax,
+    );
+
+    Ok((
+        InflationInfo {
+            mint_inflation,
+            annual_provisions,
+            // the native token has 18 decimals, the same as a Dec
+            annual_provisions_althea: ratio(annual_provisions, DEC_ONE.into()),
+            total_supply,
+            inflation,
+            inflation_min: dec_to_f64(&mint_params.inflation_min),
+            inflation_max: dec_to_f64(&mint_params.inflation_max),
+            inflation_rate_change: dec_to_f64(&mint_params.inflation_rate_change),
+            goal_bonded: dec_to_f64(&mint_params.goal_bonded),
+            blocks_per_year: mint_params.blocks_per_year,
+        },
+        apr,
+    ))
+}
+
+/// Returns the supply inflation and the staking APR it implies, see the StakingApr fields for the formulas
+fn compute_staking_apr(
+    annual_provisions: Uint256,
+    total_supply: Uint256,
+    bonded_tokens: Uint256,
+    community_tax: f64,
+) -> (f64, StakingApr) {
+    let inflation = ratio(annual_provisions, total_supply);
+    let nominal_apr = ratio(annual_provisions, bonded_tokens) * (1.0 - community_tax);
+    let real_apr = (1.0 + nominal_apr) / (1.0 + inflation) - 1.0;
+    (
+        inflation,
+        StakingApr {
+            bonded_tokens,
+            community_tax,
+            nominal_apr,
+            real_apr,
+        },
+    )
+}
+
+/// Converts a Cosmos sdk Dec string to an integer, truncating any fractional part. The gRPC interface
+/// returns these either as a decimal string or as an integer string multiplied by 10^18, anything else is None
+pub fn dec_to_uint(input: &str) -> Option<Uint256> {
+    match input.split_once('.') {
+        Some((whole, fraction)) => {
+            if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            whole.parse().ok()
+        }
+        None => {
+            let v: Uint256 = input.parse().ok()?;
+            Some(v / DEC_ONE.into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dec_to_uint() {
+        let one: Uint256 = DEC_ONE.into();
+        // decimal strings have the fraction truncated
+        assert_eq!(dec_to_uint("1234.999999999999999999"), Some(1234u16.into()));
+        assert_eq!(dec_to_uint("0.5"), Some(0u8.into()));
+        assert_eq!(dec_to_uint("7."), Some(7u8.into()));
+        // integer strings are scaled down by 10^18
+        assert_eq!(
+            dec_to_uint(&(one * 42u8.into()).to_string()),
+            Some(42u8.into())
+        );
+        assert_eq!(dec_to_uint("999999999999999999"), Some(0u8.into()));
+        assert_eq!(dec_to_uint("1500000000000000000"), Some(1u8.into()));
+
+        assert_eq!(dec_to_uint(""), None);
+        assert_eq!(dec_to_uint(".5"), None);
+        assert_eq!(dec_to_uint("-1.0"), None);
+        assert_eq!(dec_to_uint("12a"), None);
+        assert_eq!(dec_to_uint("1.2.3"), None);
+        assert_eq!(dec_to_uint("1.5x"), None);
+    }
+
+    #[test]
+    fn test_compute_staking_apr() {
+        let one: Uint256 = DEC_ONE.into();
+        // 100 minted per year over a supply of 1000 with half of it bonded and a 10% community tax
+        let (inflation, apr) = compute_staking_apr(
+            one * 100u16.into(),
+            one * 1000u16.into(),
+            one * 500u16.into(),
+            0.1,
+        );
+        assert!((inflation - 0.1).abs() < 1e-12);
+        assert!((apr.nominal_apr - 0.18).abs() < 1e-12);
+        assert!((apr.real_apr - (1.18 / 1.1 - 1.0)).abs() < 1e-12);
+        assert_eq!(apr.bonded_tokens, one * 500u16.into());
+        assert_eq!(apr.community_tax, 0.1);
+
+        // nothing bonded or no supply yields zero rates rather than dividing by zero
+        let (inflation, apr) = compute_staking_apr(one, 0u8.into(), 0u8.into(), 0.0);
+        assert_eq!(inflation, 0.0);
+        assert_eq!(apr.nominal_apr, 0.0);
+        assert_eq!(apr.real_apr, 0.0);
+    }
+}
@@ -1,11 +1,34 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod accounts;
+pub mod block_results;
+pub mod chains;
+pub mod community_pool;
 pub mod config;
+pub mod crawl;
+pub mod defillama;
 pub mod endpoints;
+pub mod events;
+pub mod evm_fees;
+pub mod gov_v1;
+pub mod governance;
+pub mod history;
+pub mod http_cache;
+pub mod ibc_denoms;
+pub mod inflation;
+pub mod microtx;
+pub mod middleware;
+pub mod module_accounts;
+pub mod settings;
+pub mod staking;
+pub mod status;
+pub mod supervisor;
+pub mod supply_report;
 pub mod tls;
 pub mod total_suppy;
 pub mod tvl;
+pub mod tx_indexer;
 
 const DEVELOPMENT: bool = cfg!(feature = "development");
 const SSL: bool = !DEVELOPMENT;
@@ -16,44 +39,125 @@ const DOMAIN: &str = if cfg!(test) || DEVELOPMENT {
 };
 /// The backend RPC port for the info server fucntions implemented in this repo
 const INFO_SERVER_PORT: u16 = 9000;
+/// The directory where historical snapshots are stored
+const DATA_DIR: &str = if cfg!(test) || DEVELOPMENT {
+    "./data"
+} else {
+    "/var/lib/althea-info-server"
+};
 
 use crate::{
     endpoints::{
-        endpoint_get_all_supply_info, endpoint_get_total_liquid_supply, endpoint_get_total_supply,
-        endpoint_get_unpriced_tvl,
+        endpoint_get_account_stats, endpoint_get_account_stats_history,
+        endpoint_get_all_supply_info, endpoint_get_chain_module_accounts,
+        endpoint_get_chain_supply_info, endpoint_get_chain_supply_report,
+        endpoint_get_chain_total_liquid_supply, endpoint_get_chain_unpriced_tvl,
+        endpoint_get_chains, endpoint_get_community_pool, endpoint_get_community_pool_history,
+        endpoint_get_defillama_historical_tvl, endpoint_get_defillama_tvl, endpoint_get_events,
+        endpoint_get_evm_activity, endpoint_get_evm_fees, endpoint_get_governance_participation,
+        endpoint_get_governance_proposals, endpoint_get_inflation, endpoint_get_inflation_history,
+        endpoint_get_microtx_volume, endpoint_get_module_accounts, endpoint_get_staking_apr,
+        endpoint_get_staking_apr_history, endpoint_get_staking_info, endpoint_get_status,
+        endpoint_get_supply_report, endpoint_get_total_liquid_supply, endpoint_get_total_supply,
+        endpoint_get_unpriced_tvl, endpoint_get_unregistered_assets,
     },
     tls::{load_certs, load_private_key},
 };
-use actix_cors::Cors;
-use actix_web::{App, HttpServer};
+use actix_web::{
+    middleware::{from_fn, Compress},
+    App, HttpServer,
+};
+use chains::{check_chains, get_chains};
+use community_pool::community_pool_worker;
 use env_logger::Env;
-use log::info;
+use evm_fees::evm_indexer_worker;
+use governance::governance_worker;
+use inflation::inflation_worker;
+use log::{error, info};
+use middleware::{access_log, check_cors_origins, cors, rate_limit};
 use rustls::ServerConfig;
-use total_suppy::chain_total_supply_thread;
+use staking::staking_info_worker;
+use supervisor::Supervisor;
+use total_suppy::chain_supply_worker;
+use tvl::tvl_snapshot_worker;
+use tx_indexer::tx_indexer_worker;
 
 pub const ALTHEA_NODE_GRPC: &str = "https://rpc.althea.zone:9090";
+pub const ALTHEA_EVM_RPC: &str = "https://rpc.althea.zone:8545";
+pub const ALTHEA_TENDERMINT_RPC: &str = "https://rpc.althea.zone:26657";
 pub const ALTHEA_PREFIX: &str = "althea";
 pub const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// How long background workers are given to stop on shutdown
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     openssl_probe::init_ssl_cert_env_vars();
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-    // starts a background thread for downloading transactions
-    chain_total_supply_thread();
+    if let Err(e) = check_chains().and_then(|_| check_cors_origins()) {
+        error!("{e}");
+        return Err(std::io::Error::other(e));
+    }
+    let mut supervisor = Supervisor::new();
+    // crawls every account for supply info and keeps it up to date from block events
+    for chain in get_chains() {
+        supervisor.spawn(format!("supply:{}", chain.chain_id), move || {
+            chain_supply_worker(chain)
+        });
+    }
+    // periodically stores TVL snapshots for historical queries
+    supervisor.spawn("tvl_snapshot", tvl_snapshot_worker);
+    // refreshes staking statistics
+    supervisor.spawn("staking", staking_info_worker);
+    // refreshes inflation and staking APR estimates
+    supervisor.spawn("inflation", inflation_worker);
+    // refreshes governance proposals and records validator votes
+    supervisor.spawn("governance", governance_worker);
+    // records community pool inflows and spends
+    supervisor.spawn("community_pool", community_pool_worker);
+    // scans new blocks for transaction statistics
+    supervisor.spawn("tx_indexer", tx_indexer_worker);
+    // scans new EVM blocks for fee and activity statistics
+    supervisor.spawn("evm_indexer", evm_indexer_worker);
 
     let info_server = HttpServer::new(move || {
         App::new()
-            .wrap(
-                Cors::default()
-                    .allow_any_origin()
-                    .allow_any_header()
-                    .allow_any_method(),
-            )
+            .wrap(Compress::default())
+            .wrap(from_fn(rate_limit))
+            // outside the rate limit so browsers can read 429 responses
+            .wrap(cors())
+            .wrap(from_fn(access_log))
             .service(endpoint_get_total_supply)
             .service(endpoint_get_total_liquid_supply)
             .service(endpoint_get_all_supply_info)
             .service(endpoint_get_unpriced_tvl)
+            .service(endpoint_get_defillama_tvl)
+            .service(endpoint_get_defillama_historical_tvl)
+            .service(endpoint_get_unregistered_assets)
+            .service(endpoint_get_staking_info)
+            .service(endpoint_get_inflation)
+            .service(endpoint_get_inflation_history)
+            .service(endpoint_get_staking_apr)
+            .service(endpoint_get_staking_apr_history)
+            .service(endpoint_get_governance_proposals)
+            .service(endpoint_get_governance_participation)
+            .service(endpoint_get_microtx_volume)
+            .service(endpoint_get_evm_fees)
+            .service(endpoint_get_evm_activity)
+            .service(endpoint_get_account_stats)
+            .service(endpoint_get_account_stats_history)
+            .service(endpoint_get_community_pool)
+            .service(endpoint_get_community_pool_history)
+            .service(endpoint_get_module_accounts)
+            .service(endpoint_get_supply_report)
+            .service(endpoint_get_status)
+            .service(endpoint_get_chains)
+            .service(endpoint_get_chain_supply_info)
+            .service(endpoint_get_chain_total_liquid_supply)
+            .service(endpoint_get_chain_module_accounts)
+            .service(endpoint_get_chain_supply_report)
+            .service(endpoint_get_chain_unpriced_tvl)
+            .service(endpoint_get_events)
     });
 
     let info_server = if SSL {
@@ -72,7 +176,9 @@ async fn main() -> std::io::Result<()> {
         info_server.bind(format!("{DOMAIN}:{INFO_SERVER_PORT}"))?
     };
 
+    // stops on SIGINT, SIGTERM or SIGQUIT once in flight requests have finished
     info_server.run().await?;
+    supervisor.shutdown(SHUTDOWN_TIMEOUT).await;
 
     Ok(())
 }
@@ -0,0 +1,248 @@
+//! MicroTx volume statistics, microtxs are the Althea L1 microtx module's low overhead payments. Volume is
+//! aggregated per day and denom from the blocks scanned by the tx indexer
+
+use crate::history::day_start;
+use althea_proto::althea::microtx::v1::MsgMicrotx;
+use cosmos_sdk_proto_althea::tendermint::abci::Event;
+use log::warn;
+use num256::Uint256;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The type url of the microtx module's transfer message
+pub const MSG_MICROTX_TYPE_URL: &str = "/althea.microtx.v1.MsgMicrotx";
+/// The typed event emitted when the microtx module collects its fee
+const FEE_COLLECTED_EVENT: &str = "althea.microtx.v1.EventMicrotxFeeCollected";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MicrotxStats {
+    /// The number of microtxs sent in this denom
+    pub count: u64,
+    /// The total amount transferred, not including fees
+    pub volume: Uint256,
+    /// The total fees collected by the microtx module
+    pub fees: Uint256,
+}
+
+impl MicrotxStats {
+    fn add(&mut self, other: &MicrotxStats) {
+        self.count += other.count;
+        self.volume += other.volume;
+        self.fees += other.fees;
+    }
+}
+
+/// Start of day unix timestamp (UTC) -> denom -> stats
+pub type MicrotxVolume = BTreeMap<u64, BTreeMap<String, MicrotxStats>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MicrotxDay {
+    /// Unix timestamp of the start of this day in UTC
+    pub day: u64,
+    pub denoms: BTreeMap<String, MicrotxStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MicrotxVolumeReport {
+    /// The last block included in these statistics
+    pub last_indexed_height: u64,
+    /// Totals over every day in the requested range
+    pub total: BTreeMap<String, MicrotxStats>,
+    /// Days with at least one microtx, oldest first
+    pub days: Vec<MicrotxDay>,
+}
+
+/// Coin as serialized in typed event attributes
+#[derive(Deserialize)]
+struct EventCoin {
+    denom: String,
+    amount: String,
+}
+
+/// Adds a successful transaction's microtx messages and the fees it paid to the daily volume. A malformed amount
+/// is an error and may leave the transaction partially recorded, so callers record into a scratch volume and only
+/// merge it once the whole block succeeded
+pub fn record_microtxs(
+    volume: &mut MicrotxVolume,
+    timestamp: u64,
+    msgs: &[MsgMicrotx],
+    events: &[Event],
+) -> Result<(), String> {
+    let day = volume.entry(day_start(timestamp)).or_default();
+    for msg in msgs {
+        let amount = match &msg.amount {
+            Some(a) => a,
+            None => continue,
+        };
+        let stats = day.entry(amount.denom.clone()).or_default();
+        stats.count += 1;
+        stats.volume += amount
+            .amount
+            .parse()
+            .map_err(|_| format!("Invalid microtx amount {}", amount.amount))?;
+    }
+
+    for event in events.iter().filter(|e| e.r#type == FEE_COLLECTED_EVENT) {
+        for attribute in event.attributes.iter().filter(|a| a.key == b"fee") {
+            let fees: Vec<EventCoin> = match serde_json::from_slice(&attribute.value) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("Failed to parse microtx fee event with {:?}", e);
+                    continue;
+                }
+            };
+            for fee in fees {
+                let stats = day.entry(fee.denom).or_default();
+                stats.fees += fee
+                    .amount
+                    .parse()
+                    .map_err(|_| format!("Invalid microtx fee {}", fee.amount))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Adds every day of `other` to `volume`
+pub fn merge_microtx_volume(volume: &mut MicrotxVolume, other: MicrotxVolume) {
+    for (day, denoms) in other {
+        let day = volume.entry(day).or_default();
+        for (denom, stats) in denoms {
+            day.entry(denom).or_default().add(&stats);
+        }
+    }
+}
+
+/// Selects the days between `from` and `to` inclusive, optionally restricted to a single denom
+pub fn query_microtx_volume(
+    volume: &MicrotxVolume,
+    last_indexed_height: u64,
+    from: Option<u64>,
+    to: Option<u64>,
+    denom: Option<&str>,
+) -> MicrotxVolumeReport {
+    let from = day_start(from.unwrap_or(0));
+    let to = to.unwrap_or(u64::MAX);
+
+    let mut total: BTreeMap<String, MicrotxStats> = BTreeMap::new();
+    let mut days = Vec::new();
+    // BTreeMap::range panics on an inverted range
+    let selected = if from <= to {
+        Some(volume.range(from..=to))
+    } else {
+        None
+    };
+    for (day, stats) in selected.into_iter().flatten() {
+        let denoms: BTreeMap<String, MicrotxStats> = stats
+            .iter()
+            .filter(|(d, _)| denom.is_none() || denom == Some(d.as_str()))
+            .map(|(d, s)| (d.clone(), s.clone()))
+            .collect();
+        if denoms.is_empty() {
+            continue;
+        }
+        for (d, s) in denoms.iter() {
+            total.entry(d.clone()).or_default().add(s);
+        }
+        days.push(MicrotxDay { day: *day, denoms });
+    }
+
+    MicrotxVolumeReport {
+        last_indexed_height,
+        total,
+        days,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::SECONDS_PER_DAY;
+    use cosmos_sdk_proto_althea::cosmos::base::v1beta1::Coin;
+    use cosmos_sdk_proto_althea::tendermint::abci::EventAttribute;
+
+    #[test]
+    fn test_microtx_volume() {
+        let msg = |denom: &str, amount: &str| MsgMicrotx {
+            sender: String::new(),
+            receiver: String::new(),
+            amount: Some(Coin {
+                denom: denom.to_string(),
+                amount: amount.to_string(),
+            }),
+        };
+        let fee_event = |fee: &str| Event {
+            r#type: FEE_COLLECTED_EVENT.to_string(),
+            attributes: vec![EventAttribute {
+                key: b"fee".to_vec(),
+                value: fee.as_bytes().to_vec(),
+                index: false,
+            }],
+        };
+
+        let mut volume = MicrotxVolume::new();
+        record_microtxs(
+            &mut volume,
+            SECONDS_PER_DAY + 5,
+            &[msg("aalthea", "1000"), msg("uusdc", "50")],
+            &[fee_event(r#"[{"denom":"aalthea","amount":"10"}]"#)],
+        )
+        .unwrap();
+        record_microtxs(
+            &mut volume,
+            SECONDS_PER_DAY * 3 + 100,
+            &[msg("aalthea", "500")],
+            &[fee_event(r#"[{"denom":"aalthea","amount":"5"}]"#)],
+        )
+        .unwrap();
+
+        let all = query_microtx_volume(&volume, 10, None, None, None);
+        assert_eq!(all.days.len(), 2);
+        assert_eq!(all.days[0].day, SECONDS_PER_DAY);
+        assert_eq!(all.total["aalthea"].count, 2);
+        assert_eq!(all.total["aalthea"].volume, 1500u32.into());
+        assert_eq!(all.total["aalthea"].fees, 15u32.into());
+        assert_eq!(all.total["uusdc"].count, 1);
+
+        // from is rounded down to the start of its day
+        let later = query_microtx_volume(&volume, 10, Some(SECONDS_PER_DAY * 3 + 50), None, None);
+        assert_eq!(later.days.len(), 1);
+        assert_eq!(later.total["aalthea"].volume, 500u32.into());
+
+        let usdc =
+            query_microtx_volume(&volume, 10, None, Some(SECONDS_PER_DAY * 2), Some("uusdc"));
+        assert_eq!(usdc.days.len(), 1);
+        assert_eq!(usdc.total.len(), 1);
+        assert_eq!(usdc.total["uusdc"].volume, 50u32.into());
+
+        // a range ending before the day `from` is rounded down to is empty
+        let inverted = query_microtx_volume(
+            &volume,
+            10,
+            Some(SECONDS_PER_DAY * 2),
+            Some(SECONDS_PER_DAY),
+            None,
+        );
+        assert!(inverted.days.is_empty());
+        assert!(inverted.total.is_empty());
+
+        // malformed amounts and fees fail rather than counting as zero
+        let mut scratch = MicrotxVolume::new();
+        assert!(record_microtxs(&mut scratch, 0, &[msg("aalthea", "1.5")], &[]).is_err());
+        assert!(record_microtxs(
+            &mut scratch,
+            0,
+            &[],
+            &[fee_event(r#"[{"denom":"aalthea","amount":"x"}]"#)]
+        )
+        .is_err());
+
+        // merging adds to the days already recorded
+        let mut block = MicrotxVolume::new();
+        record_microtxs(&mut block, SECONDS_PER_DAY, &[msg("aalthea", "1")], &[]).unwrap();
+        merge_microtx_volume(&mut volume, block);
+        let merged = query_microtx_volume(&volume, 10, None, None, Some("aalthea"));
+        assert_eq!(merged.total["aalthea"].count, 3);
+        assert_eq!(merged.total["aalthea"].volume, 1501u32.into());
+    }
+}
@@ -0,0 +1,290 @@
+//! Middleware applied to every request: per client IP rate limiting, access logs and CORS. Several endpoints
+//! query the chain on every request, so without a limit a single client can overload the server and its nodes
+
+use crate::settings::get_settings;
+use actix_cors::Cors;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Uri};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpRequest, HttpResponse};
+use log::info;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How many requests are checked between removing the buckets of clients that have gone idle
+const PRUNE_INTERVAL: u64 = 10_000;
+
+/// A token bucket per client IP, each request takes a token and tokens refill at a fixed rate up to the burst size
+pub struct IpRateLimiter {
+    per_second: f64,
+    burst: f64,
+    buckets: HashMap<IpAddr, Bucket>,
+    checks: u64,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl IpRateLimiter {
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        IpRateLimiter {
+            per_second: per_minute as f64 / 60.0,
+            burst: burst.max(1) as f64,
+            buckets: HashMap::new(),
+            checks: 0,
+        }
+    }
+
+    /// Takes a token from the client's bucket, returning how long until the next token if it is empty
+    pub fn check(&mut self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        self.checks += 1;
+        if self.checks.is_multiple_of(PRUNE_INTERVAL) {
+            self.prune(now);
+        }
+
+        let bucket = self.buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = refill(bucket, now, self.per_second, self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ))
+        }
+    }
+
+    /// Removes full buckets, a client without one is given a full bucket anyway
+    fn prune(&mut self, now: Instant) {
+        let (per_second, burst) = (self.per_second, self.burst);
+        self.buckets
+            .retain(|_, b| refill(b, now, per_second, burst) < burst);
+    }
+}
+
+fn refill(bucket: &Bucket, now: Instant, per_second: f64, burst: f64) -> f64 {
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    (bucket.tokens + elapsed * per_second).min(burst)
+}
+
+lazy_static! {
+    /// None if rate limiting is disabled
+    static ref RATE_LIMITER: Option<Mutex<IpRateLimiter>> = {
+        let settings = get_settings();
+        match settings.rate_limit_per_minute {
+            0 => None,
+            v => Some(Mutex::new(IpRateLimiter::new(v, settings.rate_limit_burst))),
+        }
+    };
+}
+
+/// Finds the client IP, if the connection is from a trusted proxy the X-Forwarded-For header is followed back from
+/// the most recent hop until an address which isn't a trusted proxy. Entries added by untrusted hops can be forged
+/// by the client and are never used
+pub fn resolve_client_ip(peer: IpAddr, forwarded_for: Option<&str>, trusted: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+    for hop in forwarded_for.into_iter().flat_map(|v| v.split(',')).rev() {
+        if !trusted.contains(&client) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+pub fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let forwarded_for = req
+        .headers()
+        .get(header::X_FORWARDED_FOR)
+        .and_then(|v| v.to_str().ok());
+    Some(resolve_client_ip(
+        peer,
+        forwarded_for,
+        &get_settings().trusted_proxies.0,
+    ))
+}
+
+/// Rejects requests from clients over the rate limit with 429 Too Many Requests
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if let (Some(limiter), Some(ip)) = (RATE_LIMITER.as_ref(), client_ip(req.request())) {
+        let result = limiter.lock().unwrap().check(ip, Instant::now());
+        if let Err(wait) = result {
+            let response = HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, wait.as_secs_f64().ceil() as u64))
+                .json("Too many requests, please slow down");
+            return Ok(req.into_response(response));
+        }
+    }
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+#[derive(Debug, Serialize)]
+struct AccessLogEntry {
+    client_ip: Option<IpAddr>,
+    method: String,
+    path: String,
+    query: String,
+    status: u16,
+    duration_ms: u128,
+    user_agent: Option<String>,
+}
+
+/// Logs every request as a json line under the `access` log target
+pub async fn access_log(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let start = Instant::now();
+    let client_ip = client_ip(req.request());
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let query = req.query_string().to_string();
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    let response = next.call(req).await;
+    let status = match &response {
+        Ok(r) => r.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    let entry = AccessLogEntry {
+        client_ip,
+        method,
+        path,
+        query,
+        status: status.as_u16(),
+        duration_ms: start.elapsed().as_millis(),
+        user_agent,
+    };
+    if let Ok(entry) = serde_json::to_string(&entry) {
+        info!(target: "access", "{}", entry);
+    }
+    response
+}
+
+/// Checks CORS_ORIGINS, called on startup since actix-cors only reports an invalid origin when the server's
+/// workers start, without naming it
+pub fn check_cors_origins() -> Result<(), String> {
+    check_origins(&get_settings().cors_origins.0)
+}
+
+/// Each origin must be a scheme and host with an optional port and no path, as browsers send them
+fn check_origins(origins: &[String]) -> Result<(), String> {
+    for origin in origins.iter().filter(|o| *o != "*") {
+        let valid = match origin.parse::<Uri>() {
+            Ok(uri) => {
+                // the parsed path of an origin is always /, so a trailing slash is checked on the string
+                uri.scheme().is_some()
+                    && uri.host().is_some()
+                    && uri.path() == "/"
+                    && uri.query().is_none()
+                    && !origin.ends_with('/')
+            }
+            Err(_) => false,
+        };
+        if !valid {
+            return Err(format!(
+                "Invalid CORS_ORIGINS entry {origin:?}, expected an origin such as https://info.althea.link"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Allows the origins in CORS_ORIGINS, or any origin if it is empty or contains *
+pub fn cors() -> Cors {
+    let origins = &get_settings().cors_origins.0;
+    let cors = if origins.is_empty() || origins.iter().any(|o| o == "*") {
+        Cors::default().allow_any_origin()
+    } else {
+        origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+    };
+    cors.allow_any_header().allow_any_method()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::CommaList;
+
+    #[test]
+    fn test_check_origins() {
+        let origins = |v: &[&str]| v.iter().map(|o| o.to_string()).collect::<Vec<_>>();
+        assert!(check_origins(&[]).is_ok());
+        assert!(check_origins(&origins(&["*"])).is_ok());
+        assert!(check_origins(&origins(&[
+            "https://info.althea.link",
+            "http://localhost:3000"
+        ]))
+        .is_ok());
+        // a path, even a trailing slash, never matches the Origin header
+        assert!(check_origins(&origins(&["https://info.althea.link/"])).is_err());
+        assert!(check_origins(&origins(&["info.althea.link"])).is_err());
+        assert!(check_origins(&origins(&["https://a.link", "not an origin"])).is_err());
+    }
+
+    #[test]
+    fn test_ip_rate_limiter() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let mut limiter = IpRateLimiter::new(60, 2);
+        let now = Instant::now();
+        assert!(limiter.check(ip, now).is_ok());
+        assert!(limiter.check(ip, now).is_ok());
+        assert_eq!(limiter.check(ip, now), Err(Duration::from_secs(1)));
+        assert!(limiter.check(other, now).is_ok());
+        // one token per second refills
+        assert!(limiter.check(ip, now + Duration::from_secs(1)).is_ok());
+        assert!(limiter.check(ip, now + Duration::from_secs(1)).is_err());
+
+        // only idle clients are forgotten
+        limiter.prune(now + Duration::from_secs(2));
+        assert_eq!(limiter.buckets.len(), 1);
+        limiter.prune(now + Duration::from_secs(3));
+        assert!(limiter.buckets.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_client_ip() {
+        let trusted: CommaList<IpAddr> = "127.0.0.1, 10.0.0.1".parse().unwrap();
+        let trusted = &trusted.0;
+        let proxy: IpAddr = "127.0.0.1".parse().unwrap();
+        let client: IpAddr = "1.2.3.4".parse().unwrap();
+
+        // a direct client can't pick its IP
+        assert_eq!(resolve_client_ip(client, Some("5.6.7.8"), trusted), client);
+        assert_eq!(resolve_client_ip(proxy, None, trusted), proxy);
+        assert_eq!(resolve_client_ip(proxy, Some("1.2.3.4"), trusted), client);
+        // entries before the first untrusted hop may be forged
+        assert_eq!(
+            resolve_client_ip(proxy, Some("5.6.7.8, 1.2.3.4, 10.0.0.1"), trusted),
+            client
+        );
+        assert_eq!(
+            resolve_client_ip(proxy, Some("garbage, 10.0.0.1"), trusted),
+            "10.0.0.1".parse::<IpAddr>().unwrap()
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Balances held by module accounts, which the supply crawl doesn't count as liquid, and a reconciliation of
+//! the supply categories against the total supply so that supply which isn't accounted for is visible
+
+use crate::{chains::ChainConfig, crawl::QueryClients, total_suppy::ChainTotalSupplyNumbers};
+use cosmos_sdk_proto_althea::cosmos::auth::v1beta1::ModuleAccount;
+use cosmos_sdk_proto_althea::cosmos::bank::v1beta1::QueryAllBalancesRequest;
+use deep_space::client::PAGE;
+use deep_space::error::CosmosGrpcError;
+use num256::{Int256, Uint256};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+/// Module accounts holding tokens the supply crawl already counts in another category, staked and unbonding
+/// tokens are held by the staking pools and unclaimed rewards, commission and the community pool by distribution
+const COUNTED_MODULE_ACCOUNTS: [&str; 3] = [
+    "bonded_tokens_pool",
+    "not_bonded_tokens_pool",
+    "distribution",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleAccountInfo {
+    pub name: String,
+    pub address: String,
+    pub permissions: Vec<String>,
+    /// The balance of the native denom
+    pub balance: Uint256,
+    /// Denom -> balance for every registered token this account holds
+    pub token_balances: BTreeMap<String, Uint256>,
+    /// True if this balance backs tokens counted in another supply category and is left out of the
+    /// reconciliation
+    pub counted_elsewhere: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SupplyReconciliation {
+    pub total_supply: Uint256,
+    pub liquid: Uint256,
+    pub vesting: Uint256,
+    pub community_pool: Uint256,
+    /// Native balances of module accounts which are not counted elsewhere
+    pub module_balances: Uint256,
+    /// total_supply - liquid - vesting - community_pool - module_balances, may be negative if categories
+    /// overlap
+    pub unexplained_remainder: Int256,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleAccountsInfo {
+    pub module_accounts: Vec<ModuleAccountInfo>,
+    pub reconciliation: SupplyReconciliation,
+}
+
+lazy_static! {
+    /// Chain id -> module accounts
+    static ref MODULE_ACCOUNTS: Arc<RwLock<HashMap<String, ModuleAccountsInfo>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+}
+
+pub fn set_module_accounts_info(chain_id: &str, input: ModuleAccountsInfo) {
+    let mut r = MODULE_ACCOUNTS.write().unwrap();
+    r.insert(chain_id.to_string(), input);
+}
+
+pub fn get_module_accounts_info(chain_id: &str) -> Option<ModuleAccountsInfo> {
+    MODULE_ACCOUNTS.read().unwrap().get(chain_id).cloned()
+}
+
+/// Gets the registered token balances of module accounts found during the supply crawl, which has already
+/// queried their native balance
+pub async fn get_module_account_info(
+    chain: &ChainConfig,
+    accounts: Vec<(ModuleAccount, Uint256)>,
+) -> Result<Vec<ModuleAccountInfo>, CosmosGrpcError> {
+    let mut bankrpc = QueryClients::connect(chain.grpc()).await?.bank;
+
+    let mut result = Vec::new();
+    for (account, balance) in accounts {
+        let address = account.base_account.map(|b| b.address).unwrap_or_default();
+        let mut token_balances = BTreeMap::new();
+        for coin in bankrpc
+            .all_balances(QueryAllBalancesRequest {
+                address: address.clone(),
+                pagination: PAGE,
+            })
+            .await?
+            .into_inner()
+            .balances
+        {
+            if chain.get_token(&coin.denom).is_some() {
+                let amount: Uint256 = coin.amount.parse().map_err(|_| {
+                    CosmosGrpcError::BadResponse(format!(
+                        "Invalid {} balance {} for module account {}",
+                        coin.denom, coin.amount, account.name
+                    ))
+                })?;
+                token_balances.insert(coin.denom, amount);
+            }
+        }
+        result.push(ModuleAccountInfo {
+            counted_elsewhere: COUNTED_MODULE_ACCOUNTS.contains(&account.name.as_str()),
+            name: account.name,
+            address,
+            permissions: account.permissions,
+            balance,
+            token_balances,
+        });
+    }
+    result.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(result)
+}
+
+pub fn reconcile_supply(
+    supply: &ChainTotalSupplyNumbers,
+    module_accounts: &[ModuleAccountInfo],
+) -> SupplyReconciliation {
+    let module_balances = module_accounts
+        .iter()
+        .filter(|m| !m.counted_elsewhere)
+        .fold(0u8.into(), |acc: Uint256, m| acc + m.balance);
+    let explained =
+        supply.total_liquid_supply + supply.total_vesting + supply.community_pool + module_balances;
+    let unexplained_remainder = if supply.total_supply >= explained {
+        to_int(supply.total_supply - explained)
+    } else {
+        Int256::from(0) - to_int(explained - supply.total_supply)
+    };
+    SupplyReconciliation {
+        total_supply: supply.total_supply,
+        liquid: supply.total_liquid_supply,
+        vesting: supply.total_vesting,
+        community_pool: supply.community_pool,
+        module_balances,
+        unexplained_remainder,
+    }
+}
+
+fn to_int(input: Uint256) -> Int256 {
+    // token supplies are far below 2^255
+    input.to_int256().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconcile_supply() {
+        let zero: Uint256 = 0u8.into();
+        let supply = ChainTotalSupplyNumbers {
+            total_supply: 1000u32.into(),
+            community_pool: 100u32.into(),
+            total_liquid_supply: 600u32.into(),
+            total_liquid_balances: zero,
+            total_unclaimed_rewards: zero,
+            total_unclaimed_other_rewards: BTreeMap::new(),
+            total_unclaimed_commission: zero,
+            total_nonvesting_staked: zero,
+            total_vesting: 200u32.into(),
+            total_vesting_staked: zero,
+            total_vested: zero,
+            total_unbonding: zero,
+            total_nonvesting_unbonding: zero,
+            total_vesting_unbonding: zero,
+            timestamp: 0,
+            block_age: 0,
+        };
+        let module = |name: &str, balance: u32| ModuleAccountInfo {
+            name: name.to_string(),
+            address: String::new(),
+            permissions: Vec::new(),
+            balance: balance.into(),
+            token_balances: BTreeMap::new(),
+            counted_elsewhere: COUNTED_MODULE_ACCOUNTS.contains(&name),
+        };
+
+        let reconciliation = reconcile_supply(
+            &supply,
+            &[module("bonded_tokens_pool", 400), module("erc20", 50)],
+        );
+        assert_eq!(reconciliation.module_balances, 50u32.into());
+        assert_eq!(reconciliation.unexplained_remainder, Int256::from(50));
+
+        let reconciliation = reconcile_supply(&supply, &[module("erc20", 150)]);
+        assert_eq!(reconciliation.unexplained_remainder, Int256::from(-50));
+    }
+}
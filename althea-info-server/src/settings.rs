@@ -0,0 +1,198 @@
+//! Runtime settings, read once from environment variables at startup. Every setting has a default so the
+//! server runs without any configuration, invalid values are logged and replaced with the default
+
+use log::{error, info};
+use std::env;
+use std::fmt::{Debug, Display};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// The highest request rate the supply crawl can be limited to, a microsecond between requests
+const MAX_CRAWL_REQUESTS_PER_SECOND: u64 = 1_000_000;
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// SUPPLY_DISCREPANCY_THRESHOLD, the largest relative difference between the total supply and the sum of
+    /// the supply categories before a supply computation is marked as suspect and not published
+    pub supply_discrepancy_threshold: f64,
+    /// MAX_BLOCK_AGE, supply numbers computed while the node's latest block was older than this many seconds
+    /// are not served
+    pub max_block_age: u64,
+    /// MAX_SUPPLY_AGE, supply numbers computed more than this many seconds ago are not served
+    pub max_supply_age: u64,
+    /// SUPPLY_UPDATE_INTERVAL, how many seconds to wait between updating supply info from the accounts touched
+    /// by new blocks
+    pub supply_update_interval: u64,
+    /// FULL_SUPPLY_CRAWL_INTERVAL, how many seconds apart full crawls of every account are, these correct any
+    /// drift from the incremental updates
+    pub full_supply_crawl_interval: u64,
+    /// CRAWL_CONCURRENCY, how many batches of accounts the supply crawl queries at once, each over its own
+    /// connection
+    pub crawl_concurrency: usize,
+    /// CRAWL_BATCH_SIZE, how many accounts are queried one after another in each batch
+    pub crawl_batch_size: usize,
+    /// CRAWL_MAX_REQUESTS_PER_SECOND, the most gRPC requests per second the supply crawl makes across every
+    /// batch, 0 for no limit, at most MAX_CRAWL_REQUESTS_PER_SECOND
+    pub crawl_max_requests_per_second: u64,
+    /// REWARDS_ROUNDING, per_account to truncate each account's unclaimed rewards to whole tokens before adding
+    /// them up, or total to add up the exact amounts and truncate the total
+    pub rewards_rounding: RewardsRounding,
+    /// CHAINS_CONFIG, path to a JSON file listing chains to serve supply info for besides Althea L1, empty for
+    /// none
+    pub chains_config: String,
+    /// CACHE_MAX_AGE, how many seconds clients and CDNs may cache supply info and history responses for before
+    /// revalidating them
+    pub cache_max_age: u32,
+    /// RATE_LIMIT_PER_MINUTE, how many requests per minute each client IP may make on average, 0 for no limit
+    pub rate_limit_per_minute: u32,
+    /// RATE_LIMIT_BURST, how many requests a client IP may make at once after being idle
+    pub rate_limit_burst: u32,
+    /// TRUSTED_PROXIES, comma separated IPs of reverse proxies whose X-Forwarded-For header is used to find the
+    /// client IP for rate limiting and access logs
+    pub trusted_proxies: CommaList<IpAddr>,
+    /// CORS_ORIGINS, comma separated origins allowed to make cross origin requests, empty for any origin
+    pub cors_origins: CommaList<String>,
+}
+
+/// A comma separated list setting, an empty string is an empty list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommaList<T>(pub Vec<T>);
+
+impl<T> Default for CommaList<T> {
+    fn default() -> Self {
+        CommaList(Vec::new())
+    }
+}
+
+impl<T: FromStr> FromStr for CommaList<T> {
+    type Err = T::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(CommaList)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewardsRounding {
+    PerAccount,
+    Total,
+}
+
+impl FromStr for RewardsRounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "per_account" => Ok(RewardsRounding::PerAccount),
+            "total" => Ok(RewardsRounding::Total),
+            _ => Err("Expected per_account or total".to_string()),
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            supply_discrepancy_threshold: 0.02,
+            max_block_age: 600,
+            // incremental updates depend on the block results endpoint, if it's unavailable supply info is
+            // only updated by the daily full crawl, allow one of those to fail
+            max_supply_age: 2 * 86400,
+            supply_update_interval: 60,
+            full_supply_crawl_interval: 86400,
+            crawl_concurrency: 8,
+            crawl_batch_size: 500,
+            crawl_max_requests_per_second: 400,
+            rewards_rounding: RewardsRounding::PerAccount,
+            chains_config: String::new(),
+            // supply info is updated about this often
+            cache_max_age: 60,
+            rate_limit_per_minute: 120,
+            rate_limit_burst: 30,
+            trusted_proxies: CommaList::default(),
+            cors_origins: CommaList::default(),
+        }
+    }
+}
+
+impl Settings {
+    fn from_env() -> Self {
+        let default = Settings::default();
+        let settings = Settings {
+            supply_discrepancy_threshold: env_or(
+                "SUPPLY_DISCREPANCY_THRESHOLD",
+                default.supply_discrepancy_threshold,
+            ),
+            max_block_age: env_or("MAX_BLOCK_AGE", default.max_block_age),
+            max_supply_age: env_or("MAX_SUPPLY_AGE", default.max_supply_age),
+            supply_update_interval: env_or(
+                "SUPPLY_UPDATE_INTERVAL",
+                default.supply_update_interval,
+            ),
+            full_supply_crawl_interval: env_or(
+                "FULL_SUPPLY_CRAWL_INTERVAL",
+                default.full_supply_crawl_interval,
+            ),
+            crawl_concurrency: env_or("CRAWL_CONCURRENCY", default.crawl_concurrency),
+            crawl_batch_size: env_or("CRAWL_BATCH_SIZE", default.crawl_batch_size),
+            crawl_max_requests_per_second: env_or_at_most(
+                "CRAWL_MAX_REQUESTS_PER_SECOND",
+                default.crawl_max_requests_per_second,
+                MAX_CRAWL_REQUESTS_PER_SECOND,
+            ),
+            rewards_rounding: env_or("REWARDS_ROUNDING", default.rewards_rounding),
+            chains_config: env_or("CHAINS_CONFIG", default.chains_config),
+            cache_max_age: env_or("CACHE_MAX_AGE", default.cache_max_age),
+            rate_limit_per_minute: env_or("RATE_LIMIT_PER_MINUTE", default.rate_limit_per_minute),
+            rate_limit_burst: env_or("RATE_LIMIT_BURST", default.rate_limit_burst),
+            trusted_proxies: env_or("TRUSTED_PROXIES", default.trusted_proxies),
+            cors_origins: env_or("CORS_ORIGINS", default.cors_origins),
+        };
+        info!("Using settings {:?}", settings);
+        settings
+    }
+}
+
+lazy_static! {
+    static ref SETTINGS: Settings = Settings::from_env();
+}
+
+pub fn get_settings() -> &'static Settings {
+    &SETTINGS
+}
+
+/// Reads and parses an environment variable, returning the default if it is unset or invalid
+fn env_or<T: FromStr>(name: &str, default: T) -> T
+where
+    T::Err: Debug,
+{
+    match env::var(name) {
+        Ok(v) => match v.parse() {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Invalid value {v} for {name} with {e:?}, using the default");
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+/// Like env_or, but values above `max` are also replaced with the default
+fn env_or_at_most<T: FromStr + PartialOrd + Display + Copy>(name: &str, default: T, max: T) -> T
+where
+    T::Err: Debug,
+{
+    let v = env_or(name, default);
+    if v > max {
+        error!("Invalid value {v} for {name}, the most is {max}, using the default");
+        default
+    } else {
+        v
+    }
+}
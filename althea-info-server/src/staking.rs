@@ -0,0 +1,236 @@
+//! Staking statistics, the bonded ratio, validator set breakdown and decentralization metrics that are
+//! otherwise only available by manually combining several staking module queries
+
+use crate::{total_suppy::ALTHEA_DENOM, ALTHEA_NODE_GRPC, ALTHEA_PREFIX, REQUEST_TIMEOUT};
+use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::query_client::QueryClient as StakingQueryClient;
+use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::{
+    BondStatus, QueryPoolRequest, QueryValidatorsRequest, Validator,
+};
+use deep_space::client::PAGE;
+use deep_space::error::CosmosGrpcError;
+use deep_space::Contact;
+use log::{error, info};
+use num256::Uint256;
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::sleep;
+
+// update every 10 minutes
+const LOOP_TIME: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StakingInfo {
+    /// Tokens bonded to active validators
+    pub bonded_tokens: Uint256,
+    /// Tokens delegated to inactive validators or currently unbonding
+    pub not_bonded_tokens: Uint256,
+    /// The total supply of the staking denom
+    pub total_supply: Uint256,
+    /// bonded_tokens / total_supply
+    pub bonded_ratio: f64,
+    /// Validators in the active set
+    pub active_validators: usize,
+    /// Validators outside the active set, including jailed validators
+    pub inactive_validators: usize,
+    pub jailed_validators: usize,
+    /// The minimum number of active validators that together hold more than 1/3 of the voting power
+    pub nakamoto_coefficient: usize,
+    /// Every validator sorted by tokens, largest first
+    pub validators: Vec<ValidatorInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidatorInfo {
+    pub operator_address: String,
+    pub moniker: String,
+    /// One of BOND_STATUS_BONDED, BOND_STATUS_UNBONDING or BOND_STATUS_UNBONDED
+    pub status: String,
+    pub jailed: bool,
+    pub tokens: Uint256,
+    /// The commission rate as a fraction, eg 0.05 for 5%
+    pub commission_rate: f64,
+    /// This validators share of the active set voting power, zero if not in the active set
+    pub voting_power_share: f64,
+}
+
+lazy_static! {
+    static ref STAKING_INFO: Arc<RwLock<Option<StakingInfo>>> = Arc::new(RwLock::new(None));
+}
+
+fn set_staking_info(input: StakingInfo) {
+    let mut r = STAKING_INFO.write().unwrap();
+    *r = Some(input);
+}
+
+pub fn get_staking_info() -> Option<StakingInfo> {
+    STAKING_INFO.read().unwrap().clone()
+}
+
+pub async fn staking_info_worker() {
+    loop {
+        let contact = Contact::new(ALTHEA_NODE_GRPC, REQUEST_TIMEOUT, ALTHEA_PREFIX).unwrap();
+        match compute_staking_info(&contact, ALTHEA_DENOM.to_string()).await {
+            Ok(v) => {
+                info!("Successfully updated staking info!");
+                set_staking_info(v);
+            }
+            Err(e) => error!("Failed to update staking info with {:?}", e),
+        }
+        sleep(LOOP_TIME).await;
+    }
+}
+
+pub async fn compute_staking_info(
+    contact: &Contact,
+    denom: String,
+) -> Result<StakingInfo, CosmosGrpcError> {
+    let total_supply = match contact.query_supply_of(denom.clone()).await? {
+        Some(v) => v.amount,
+        None => {
+            return Err(CosmosGrpcError::BadResponse(format!(
+                "No supply for {denom}"
+            )))
+        }
+    };
+
+    let mut stakingrpc = StakingQueryClient::connect(contact.get_url()).await?;
+    let pool = stakingrpc
+        .pool(QueryPoolRequest {})
+        .await?
+        .into_inner()
+        .pool
+        .ok_or_else(|| CosmosGrpcError::BadResponse("No staking pool".to_string()))?;
+    let bonded_tokens: Uint256 = pool
+        .bonded_tokens
+        .parse()
+        .map_err(|_| invalid_integer(&pool.bonded_tokens))?;
+    let not_bonded_tokens: Uint256 = pool
+        .not_bonded_tokens
+        .parse()
+        .map_err(|_| invalid_integer(&pool.not_bonded_tokens))?;
+
+    let validators = stakingrpc
+        .validators(QueryValidatorsRequest {
+            status: String::new(),
+            pagination: PAGE,
+        })
+        .await?
+        .into_inner()
+        .validators;
+
+    let mut active_validators = 0;
+    let mut inactive_validators = 0;
+    let mut jailed_validators = 0;
+    let mut active_tokens = Vec::new();
+    let mut validator_info = Vec::new();
+    for v in validators {
+        let tokens: Uint256 = v.tokens.parse().map_err(|_| invalid_integer(&v.tokens))?;
+        let info = get_validator_info(v, tokens, bonded_tokens);
+        if info.status == BondStatus::Bonded.as_str_name() {
+            active_validators += 1;
+            active_tokens.push(info.tokens);
+        } else {
+            inactive_validators += 1;
+        }
+        if info.jailed {
+            jailed_validators += 1;
+        }
+        validator_info.push(info);
+    }
+    validator_info.sort_by_key(|v| std::cmp::Reverse(v.tokens));
+
+    Ok(StakingInfo {
+        bonded_tokens,
+        not_bonded_tokens,
+        total_supply,
+        bonded_ratio: ratio(bonded_tokens, total_supply),
+        active_validators,
+        inactive_validators,
+        jailed_validators,
+        nakamoto_coefficient: nakamoto_coefficient(active_tokens),
+        validators: validator_info,
+    })
+}
+
+fn get_validator_info(v: Validator, tokens: Uint256, bonded_tokens: Uint256) -> ValidatorInfo {
+    let status = BondStatus::try_from(v.status).unwrap_or(BondStatus::Unspecified);
+    let commission_rate = v
+        .commission
+        .and_then(|c| c.commission_rates)
+        .map(|r| dec_to_f64(&r.rate))
+        .unwrap_or(0.0);
+    let voting_power_share = if status == BondStatus::Bonded {
+        ratio(tokens, bonded_tokens)
+    } else {
+        0.0
+    };
+    ValidatorInfo {
+        operator_address: v.operator_address,
+        moniker: v.description.map(|d| d.moniker).unwrap_or_default(),
+        status: status.as_str_name().to_string(),
+        jailed: v.jailed,
+        tokens,
+        commission_rate,
+        voting_power_share,
+    }
+}
+
+/// Computes the Nakamoto coefficient, the smallest number of validators that together control
+/// more than 1/3 of the voting power and can therefore halt the chain
+pub fn nakamoto_coefficient(mut validator_tokens: Vec<Uint256>) -> usize {
+    let total: Uint256 = validator_tokens
+        .iter()
+        .fold(0u8.into(), |acc: Uint256, t| acc + *t);
+    let threshold = total / 3u8.into();
+    validator_tokens.sort_by(|a, b| b.cmp(a));
+
+    let mut sum: Uint256 = 0u8.into();
+    for (i, tokens) in validator_tokens.iter().enumerate() {
+        sum += *tokens;
+        if sum > threshold {
+            return i + 1;
+        }
+    }
+    validator_tokens.len()
+}
+
+/// Converts a Cosmos sdk Dec string to a float, the gRPC interface returns these either as a
+/// decimal string or as an integer string multiplied by 10^18
+pub fn dec_to_f64(input: &str) -> f64 {
+    if input.contains('.') {
+        input.parse().unwrap_or(0.0)
+    } else {
+        input.parse::<f64>().unwrap_or(0.0) / 1e18
+    }
+}
+
+/// Returns numerator / denominator as a float, zero if the denominator is zero
+pub fn ratio(numerator: Uint256, denominator: Uint256) -> f64 {
+    if denominator == 0u8.into() {
+        return 0.0;
+    }
+    let numerator: f64 = numerator.to_string().parse().unwrap();
+    let denominator: f64 = denominator.to_string().parse().unwrap();
+    numerator / denominator
+}
+
+pub fn invalid_integer(input: &str) -> CosmosGrpcError {
+    CosmosGrpcError::BadResponse(format!("Invalid integer {input}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nakamoto_coefficient() {
+        let tokens = |v: &[u32]| v.iter().map(|t| (*t).into()).collect::<Vec<Uint256>>();
+        assert_eq!(nakamoto_coefficient(tokens(&[])), 0);
+        assert_eq!(nakamoto_coefficient(tokens(&[100])), 1);
+        // 40 alone is more than a third of 100
+        assert_eq!(nakamoto_coefficient(tokens(&[10, 40, 20, 30])), 1);
+        // ten equal validators need four to pass a third
+        assert_eq!(nakamoto_coefficient(tokens(&[10; 10])), 4);
+    }
+}
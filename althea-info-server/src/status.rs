@@ -0,0 +1,42 @@
+//! Reports on the background work the server is doing, for operators checking why data is missing or stale
+
+use crate::chains::get_chains;
+use crate::crawl::{get_crawl_status, CrawlStatus};
+use crate::supervisor::{get_worker_status, WorkerStatus};
+use crate::total_suppy::get_supply_height;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    /// Chain id -> supply worker status
+    pub chains: BTreeMap<String, ChainStatus>,
+    /// Worker name -> whether it is running and its most recent panic
+    pub workers: BTreeMap<String, WorkerStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainStatus {
+    /// Progress of the current or last full supply crawl
+    pub supply_crawl: Option<CrawlStatus>,
+    /// The block height the published supply info is up to date with
+    pub supply_height: Option<u64>,
+}
+
+pub fn get_status() -> Status {
+    Status {
+        chains: get_chains()
+            .iter()
+            .map(|c| {
+                (
+                    c.chain_id.clone(),
+                    ChainStatus {
+                        supply_crawl: get_crawl_status(&c.chain_id),
+                        supply_height: get_supply_height(&c.chain_id),
+                    },
+                )
+            })
+            .collect(),
+        workers: get_worker_status(),
+    }
+}
@@ -0,0 +1,315 @@
+//! Supervises the background workers. The web30 http client can't be sent between threads, so each worker runs on
+//! its own OS thread with a single threaded actix runtime, inside which the worker future is a task the supervisor
+//! awaits. A worker which panics is restarted after an increasing delay and the panic is reported in /status, on
+//! shutdown, or if the Supervisor is dropped, every worker is cancelled at its next await point
+
+use crate::history::get_current_timestamp;
+use actix_web::rt::{spawn, System};
+use futures::future::join_all;
+use log::{error, info, warn};
+use serde::Serialize;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch};
+use tokio::time::{sleep, timeout};
+
+/// How long to wait before restarting a worker the first time it fails
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(5);
+/// The longest wait before restarting a worker which keeps failing
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(600);
+/// A worker which ran for at least this long before failing is restarted after the shortest delay again
+const HEALTHY_RUN: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerState {
+    Running,
+    /// Waiting to be restarted after failing
+    Restarting,
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerStatus {
+    pub state: WorkerState,
+    /// How many times the worker has been restarted after failing
+    pub restarts: u32,
+    /// The most recent panic, None if the worker has never panicked
+    pub last_panic: Option<WorkerPanic>,
+    /// Unix timestamp of the most recent time the worker returned without panicking, None if it never has
+    pub last_exit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerPanic {
+    pub message: String,
+    /// Unix timestamp of the panic
+    pub timestamp: u64,
+}
+
+lazy_static! {
+    /// Worker name -> status
+    static ref WORKER_STATUS: Arc<RwLock<BTreeMap<String, WorkerStatus>>> =
+        Arc::new(RwLock::new(BTreeMap::new()));
+}
+
+pub fn get_worker_status() -> BTreeMap<String, WorkerStatus> {
+    WORKER_STATUS.read().unwrap().clone()
+}
+
+fn update_worker_status(name: &str, update: impl FnOnce(&mut WorkerStatus)) {
+    let mut r = WORKER_STATUS.write().unwrap();
+    let status = r.entry(name.to_string()).or_insert(WorkerStatus {
+        state: WorkerState::Running,
+        restarts: 0,
+        last_panic: None,
+        last_exit: None,
+    });
+    update(status);
+}
+
+/// Stops its workers when shut down or dropped
+pub struct Supervisor {
+    shutdown: watch::Sender<bool>,
+    /// Resolved as each worker's thread finishes
+    stopped: Vec<oneshot::Receiver<()>>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Supervisor {
+            shutdown: watch::channel(false).0,
+            stopped: Vec::new(),
+        }
+    }
+
+    /// Runs the future returned by `worker` until shutdown, calling it again if the future panics or returns
+    pub fn spawn<F, Fut>(&mut self, name: impl Into<String>, worker: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let name = name.into();
+        info!("Starting {} worker", name);
+        let shutdown = self.shutdown.subscribe();
+        let (stopped, receiver) = oneshot::channel();
+        self.stopped.push(receiver);
+        thread::spawn(move || {
+            System::new().block_on(supervise(
+                name,
+                worker,
+                shutdown,
+                RestartPolicy {
+                    min_delay: MIN_RESTART_DELAY,
+                    max_delay: MAX_RESTART_DELAY,
+                    healthy_run: HEALTHY_RUN,
+                },
+            ));
+            let _ = stopped.send(());
+        });
+    }
+
+    /// Cancels every worker, waiting up to `wait` for them to stop
+    pub async fn shutdown(self, wait: Duration) {
+        info!("Stopping background workers");
+        let _ = self.shutdown.send(true);
+        if timeout(wait, join_all(self.stopped)).await.is_err() {
+            warn!("Background workers did not stop in time");
+        }
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RestartPolicy {
+    min_delay: Duration,
+    max_delay: Duration,
+    healthy_run: Duration,
+}
+
+impl RestartPolicy {
+    /// The delay before the next restart, given the last delay and how long the worker ran before failing
+    fn next_delay(&self, last: Option<Duration>, ran_for: Duration) -> Duration {
+        match last {
+            Some(last) if ran_for < self.healthy_run => (last * 2).min(self.max_delay),
+            _ => self.min_delay,
+        }
+    }
+}
+
+async fn supervise<F, Fut>(
+    name: String,
+    worker: F,
+    mut shutdown: watch::Receiver<bool>,
+    policy: RestartPolicy,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()> + 'static,
+{
+    let mut delay = None;
+    loop {
+        update_worker_status(&name, |s| s.state = WorkerState::Running);
+        let started = Instant::now();
+        let mut task = spawn(worker());
+        let result = tokio::select! {
+            r = &mut task => r,
+            _ = shutdown_requested(&name, &mut shutdown) => {
+                task.abort();
+                let _ = task.await;
+                break;
+            }
+        };
+        match result {
+            Ok(()) => {
+                warn!("{} worker exited", name);
+                update_worker_status(&name, |s| s.last_exit = Some(get_current_timestamp()));
+            }
+            Err(e) if e.is_panic() => {
+                let message = panic_message(e.into_panic());
+                error!("{} worker panicked with {}", name, message);
+                update_worker_status(&name, |s| {
+                    s.last_panic = Some(WorkerPanic {
+                        message,
+                        timestamp: get_current_timestamp(),
+                    })
+                });
+            }
+            Err(_) => break,
+        }
+
+        let wait = policy.next_delay(delay, started.elapsed());
+        delay = Some(wait);
+        info!("Restarting {} worker in {:?}", name, wait);
+        update_worker_status(&name, |s| {
+            s.state = WorkerState::Restarting;
+            s.restarts += 1;
+        });
+        tokio::select! {
+            _ = sleep(wait) => {}
+            _ = shutdown_requested(&name, &mut shutdown) => break,
+        }
+    }
+    update_worker_status(&name, |s| s.state = WorkerState::Stopped);
+    info!("Stopped {} worker", name);
+}
+
+/// Resolves once shutdown is requested, or once the Supervisor is dropped as nothing could stop the worker after that
+async fn shutdown_requested(name: &str, shutdown: &mut watch::Receiver<bool>) {
+    if shutdown.wait_for(|stop| *stop).await.is_err() {
+        warn!("Supervisor dropped, stopping {} worker", name);
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(v) => *v,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(v) => v.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const TEST_POLICY: RestartPolicy = RestartPolicy {
+        min_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(10),
+        healthy_run: Duration::from_secs(60),
+    };
+
+    #[test]
+    fn test_restart_delay() {
+        let policy = RestartPolicy {
+            min_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(600),
+            healthy_run: Duration::from_secs(300),
+        };
+        let quick = Duration::from_secs(1);
+        assert_eq!(policy.next_delay(None, quick), Duration::from_secs(5));
+        assert_eq!(
+            policy.next_delay(Some(Duration::from_secs(5)), quick),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            policy.next_delay(Some(Duration::from_secs(400)), quick),
+            Duration::from_secs(600)
+        );
+        // a worker which ran for a while before failing again starts over from the shortest delay
+        assert_eq!(
+            policy.next_delay(Some(Duration::from_secs(600)), Duration::from_secs(300)),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_supervise_restarts() {
+        let runs = Arc::new(AtomicU32::new(0));
+        let (shutdown, receiver) = watch::channel(false);
+        let worker_runs = runs.clone();
+        let task = spawn(supervise(
+            "test".to_string(),
+            move || {
+                let run = worker_runs.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match run {
+                        0 => {}
+                        1 => panic!("run {run} failed"),
+                        _ => sleep(Duration::from_secs(60)).await,
+                    }
+                }
+            },
+            receiver,
+            TEST_POLICY,
+        ));
+        while runs.load(Ordering::SeqCst) < 3 {
+            sleep(Duration::from_millis(1)).await;
+        }
+
+        let status = get_worker_status().remove("test").unwrap();
+        assert_eq!(status.state, WorkerState::Running);
+        assert_eq!(status.restarts, 2);
+        assert_eq!(status.last_panic.unwrap().message, "run 1 failed");
+        // the first run returned rather than panicking
+        assert!(status.last_exit.is_some());
+
+        shutdown.send(true).unwrap();
+        task.await.unwrap();
+        assert_eq!(
+            get_worker_status().remove("test").unwrap().state,
+            WorkerState::Stopped
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_supervise_stops_when_dropped() {
+        let (shutdown, receiver) = watch::channel(false);
+        let task = spawn(supervise(
+            "dropped".to_string(),
+            || sleep(Duration::from_secs(60)),
+            receiver,
+            TEST_POLICY,
+        ));
+        drop(shutdown);
+        timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            get_worker_status().remove("dropped").unwrap().state,
+            WorkerState::Stopped
+        );
+    }
+}
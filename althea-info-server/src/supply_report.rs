@@ -0,0 +1,139 @@
+//! A consistency check run on every supply computation. The supply categories are computed independently from
+//! the total supply, so if they don't add up to it within a threshold the computation is likely based on stale
+//! or partial data and is marked as suspect, the previous supply info is then kept instead of publishing it
+
+use crate::{module_accounts::SupplyReconciliation, staking::ratio};
+use num256::Uint256;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+/// How many of the largest categories are listed in the report
+const LARGEST_CATEGORIES: usize = 5;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SupplyReport {
+    /// The total supply according to the bank module
+    pub expected_total: Uint256,
+    /// liquid + vesting + community pool + module balances
+    pub computed_total: Uint256,
+    pub absolute_discrepancy: Uint256,
+    /// absolute_discrepancy / expected_total
+    pub relative_discrepancy: f64,
+    /// The categories contributing the most to computed_total, largest first
+    pub largest_categories: Vec<SupplyCategory>,
+    /// The relative discrepancy above which the computation is suspect
+    pub threshold: f64,
+    /// True if relative_discrepancy is above the threshold, suspect supply numbers are not published
+    pub suspect: bool,
+    /// Unix timestamp of the computation checked, which is newer than the published supply info if it was suspect
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SupplyCategory {
+    pub name: String,
+    pub amount: Uint256,
+    /// amount / computed_total
+    pub share: f64,
+}
+
+lazy_static! {
+    /// Chain id -> supply report
+    static ref SUPPLY_REPORT: Arc<RwLock<HashMap<String, SupplyReport>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+}
+
+pub fn set_supply_report(chain_id: &str, input: SupplyReport) {
+    let mut r = SUPPLY_REPORT.write().unwrap();
+    r.insert(chain_id.to_string(), input);
+}
+
+pub fn get_supply_report(chain_id: &str) -> Option<SupplyReport> {
+    SUPPLY_REPORT.read().unwrap().get(chain_id).cloned()
+}
+
+/// Builds the report from the reconciliation and the amount each account category contributed to the liquid
+/// and vesting totals
+pub fn build_supply_report(
+    reconciliation: &SupplyReconciliation,
+    account_categories: BTreeMap<String, Uint256>,
+    threshold: f64,
+    timestamp: u64,
+) -> SupplyReport {
+    let expected_total = reconciliation.total_supply;
+    let computed_total = reconciliation.liquid
+        + reconciliation.vesting
+        + reconciliation.community_pool
+        + reconciliation.module_balances;
+    let absolute_discrepancy = if expected_total > computed_total {
+        expected_total - computed_total
+    } else {
+        computed_total - expected_total
+    };
+    let relative_discrepancy = ratio(absolute_discrepancy, expected_total);
+
+    let mut categories = account_categories;
+    categories.insert("community_pool".to_string(), reconciliation.community_pool);
+    categories.insert(
+        "module_balances".to_string(),
+        reconciliation.module_balances,
+    );
+    let mut largest_categories: Vec<SupplyCategory> = categories
+        .into_iter()
+        .map(|(name, amount)| SupplyCategory {
+            name,
+            amount,
+            share: ratio(amount, computed_total),
+        })
+        .collect();
+    largest_categories.sort_by_key(|c| std::cmp::Reverse(c.amount));
+    largest_categories.truncate(LARGEST_CATEGORIES);
+
+    SupplyReport {
+        expected_total,
+        computed_total,
+        absolute_discrepancy,
+        relative_discrepancy,
+        largest_categories,
+        threshold,
+        // an empty supply can't be right either
+        suspect: relative_discrepancy > threshold || expected_total == 0u8.into(),
+        timestamp,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num256::Int256;
+
+    #[test]
+    fn test_build_supply_report() {
+        let reconciliation = SupplyReconciliation {
+            total_supply: 1000u32.into(),
+            liquid: 600u32.into(),
+            vesting: 200u32.into(),
+            community_pool: 100u32.into(),
+            module_balances: 50u32.into(),
+            unexplained_remainder: Int256::from(50),
+        };
+        let categories: BTreeMap<String, Uint256> = [
+            ("base".to_string(), 500u32.into()),
+            ("continuous_vesting".to_string(), 300u32.into()),
+        ]
+        .into();
+
+        let report = build_supply_report(&reconciliation, categories.clone(), 0.02, 100);
+        assert_eq!(report.computed_total, 950u32.into());
+        assert_eq!(report.absolute_discrepancy, 50u32.into());
+        assert_eq!(report.relative_discrepancy, 0.05);
+        assert!(report.suspect);
+        assert_eq!(report.largest_categories.len(), 4);
+        assert_eq!(report.largest_categories[0].name, "base");
+        assert_eq!(report.largest_categories[3].name, "module_balances");
+
+        let report = build_supply_report(&reconciliation, categories, 0.1, 100);
+        assert!(!report.suspect);
+    }
+}
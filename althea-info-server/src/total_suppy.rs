@@ -4,33 +4,61 @@
 //! to effectively query all the data in a reasonable amount of time and compute the result locally
 //! This code provides a generic way to compute the total liquid supply for a cosmos chain across all vesting types
 
-use crate::{ALTHEA_NODE_GRPC, ALTHEA_PREFIX, REQUEST_TIMEOUT};
-use actix_web::rt::System;
-use cosmos_sdk_proto_althea::cosmos::bank::v1beta1::query_client::QueryClient as BankQueryClient;
+use crate::{
+    accounts::{account_type_name, count_accounts, update_account_stats, AccountCounts},
+    block_results::{get_block_results, get_touched_accounts},
+    chains::{is_althea, ChainConfig},
+    crawl::{
+        finish_crawl_status, record_crawl_progress, set_crawl_total, start_crawl_status,
+        ClientPool, QueryClients, RateLimiter,
+    },
+    events::{publish_event, EventKind, SupplyInfoEvent},
+    history::get_current_timestamp,
+    module_accounts::{
+        get_module_account_info, reconcile_supply, set_module_accounts_info, ModuleAccountsInfo,
+    },
+    settings::{get_settings, RewardsRounding},
+    staking::ratio,
+    supply_report::{build_supply_report, set_supply_report, SupplyReport},
+    REQUEST_TIMEOUT,
+};
+use cosmos_sdk_proto_althea::cosmos::auth::v1beta1::QueryAccountsRequest;
 use cosmos_sdk_proto_althea::cosmos::bank::v1beta1::QueryBalanceRequest;
-use cosmos_sdk_proto_althea::cosmos::distribution::v1beta1::query_client::QueryClient as DistQueryClient;
-use cosmos_sdk_proto_althea::cosmos::distribution::v1beta1::QueryDelegationTotalRewardsRequest;
-use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::query_client::QueryClient as StakingQueryClient;
-use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::QueryDelegatorDelegationsRequest;
+use cosmos_sdk_proto_althea::cosmos::base::query::v1beta1::PageRequest;
+use cosmos_sdk_proto_althea::cosmos::distribution::v1beta1::{
+    QueryDelegationTotalRewardsRequest, QueryValidatorCommissionRequest,
+};
+use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::{
+    QueryDelegatorDelegationsRequest, QueryDelegatorUnbondingDelegationsRequest,
+    QueryValidatorsRequest, UnbondingDelegation,
+};
 use cosmos_sdk_proto_althea::cosmos::vesting::v1beta1::BaseVestingAccount;
-use deep_space::client::types::AccountType;
+use deep_space::client::types::{AccountType, LatestBlock};
 use deep_space::client::PAGE;
 use deep_space::error::CosmosGrpcError;
-use deep_space::{Coin, Contact};
-use futures::future::{join3, join_all};
+use deep_space::{Address, Coin, Contact};
+use futures::future::join4;
+use futures::stream::{self, TryStreamExt};
 use log::{error, info, trace};
 use num256::Uint256;
 use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::pin::pin;
 use std::sync::{Arc, RwLock};
-use std::thread;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
-use tonic::transport::channel::Channel;
+use tokio::time::sleep;
+use web30::jsonrpc::client::HttpClient;
 
-// update once a day
-const LOOP_TIME: Duration = Duration::from_secs(86400);
 pub const ALTHEA_DENOM: &str = "aalthea";
+/// How many accounts are requested at once when listing every account
+const ACCOUNTS_PAGE_SIZE: u64 = 20_000;
+/// How long to wait before retrying a failed full crawl, doubled for each failure in a row up to the full crawl
+/// interval
+const MIN_CRAWL_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Dec values have 18 decimal places, they are held as integers multiplied by this
+pub const DEC_ONE: u128 = 10u128.pow(18);
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct ChainTotalSupplyNumbers {
     /// The total amount of Althea tokens currently in existance including those vesting and in the community pool
     pub total_supply: Uint256,
@@ -43,6 +71,10 @@ pub struct ChainTotalSupplyNumbers {
     pub total_liquid_balances: Uint256,
     /// All tokens that are unclaimed as rewards
     pub total_unclaimed_rewards: Uint256,
+    /// Denom -> unclaimed rewards in tokens other than the native token
+    pub total_unclaimed_other_rewards: BTreeMap<String, Uint256>,
+    /// All validator commission that has not been withdrawn yet, it's counted as liquid for the operator accounts
+    pub total_unclaimed_commission: Uint256,
     /// All tokens staked, but not vesting
     pub total_nonvesting_staked: Uint256,
     /// All tokens not yet vested, including those staked
@@ -51,39 +83,310 @@ pub struct ChainTotalSupplyNumbers {
     pub total_vesting_staked: Uint256,
     /// All tokens that have vested so far
     pub total_vested: Uint256,
+    /// All tokens in the unbonding queue, these are neither staked nor in a balance until unbonding completes
+    pub total_unbonding: Uint256,
+    /// All tokens unbonding that are not vesting, these are counted in the liquid supply
+    pub total_nonvesting_unbonding: Uint256,
+    /// All tokens unbonding that are still vesting
+    pub total_vesting_unbonding: Uint256,
+    /// Unix timestamp in seconds of when these numbers were computed
+    pub timestamp: u64,
+    /// How many seconds old the node's latest block was when the computation started, a node that has fallen
+    /// behind serves outdated balances
+    pub block_age: u64,
+}
+
+/// Everything computed from the per account state
+pub struct SupplyCrawl {
+    pub supply: ChainTotalSupplyNumbers,
+    pub module_accounts: ModuleAccountsInfo,
+    /// Checks that the supply categories add up to the total supply
+    pub report: SupplyReport,
+}
+
+/// The balances of every account on chain as of a block height, built by a full crawl and then kept up to date
+/// by re-querying only the accounts touched by each new block. Every account is held in memory so that supply
+/// can be recomputed without another crawl, so memory use grows with the number of accounts on chain
+pub struct SupplyState {
+    pub chain: &'static ChainConfig,
+    /// The height up to which block events have been applied
+    pub height: u64,
+    /// Address -> account and balances
+    users: BTreeMap<String, UserInfo>,
+    /// Account counts from the last full crawl
+    pub account_counts: AccountCounts,
 }
 
 lazy_static! {
-    static ref TOTAL_SUPPLY: Arc<RwLock<Option<ChainTotalSupplyNumbers>>> =
-        Arc::new(RwLock::new(None));
+    /// Chain id -> supply info
+    static ref TOTAL_SUPPLY: Arc<RwLock<HashMap<String, ChainTotalSupplyNumbers>>> =
+        Arc::new(RwLock::new(HashMap::new()));
+    /// Chain id -> the height the supply info is up to date with
+    static ref SUPPLY_HEIGHT: Arc<RwLock<HashMap<String, u64>>> =
+        Arc::new(RwLock::new(HashMap::new()));
 }
 
-fn set_supply_info(input: ChainTotalSupplyNumbers) {
+fn set_supply_info(chain_id: &str, input: ChainTotalSupplyNumbers) {
     let mut r = TOTAL_SUPPLY.write().unwrap();
-    *r = Some(input);
+    if should_publish_supply_event(r.get(chain_id), &input, get_settings().max_block_age) {
+        publish_event(
+            EventKind::SupplyInfo,
+            &SupplyInfoEvent {
+                chain_id,
+                supply: &input,
+            },
+        );
+    }
+    r.insert(chain_id.to_string(), input);
+}
+
+/// Supply info is only pushed to clients if the numbers changed and were computed from an up to date node, the
+/// timestamps change on every update. Suspect supply info is never set in the first place
+fn should_publish_supply_event(
+    previous: Option<&ChainTotalSupplyNumbers>,
+    current: &ChainTotalSupplyNumbers,
+    max_block_age: u64,
+) -> bool {
+    if current.block_age > max_block_age {
+        return false;
+    }
+    match previous {
+        Some(previous) => {
+            let unchanged = ChainTotalSupplyNumbers {
+                timestamp: current.timestamp,
+                block_age: current.block_age,
+                ..previous.clone()
+            };
+            unchanged != *current
+        }
+        None => true,
+    }
 }
 
-pub fn get_supply_info() -> Option<ChainTotalSupplyNumbers> {
-    TOTAL_SUPPLY.read().unwrap().clone()
+pub fn get_supply_info(chain_id: &str) -> Option<ChainTotalSupplyNumbers> {
+    TOTAL_SUPPLY.read().unwrap().get(chain_id).cloned()
 }
 
-pub fn chain_total_supply_thread() {
-    info!("Starting supply calculation thread");
+fn set_supply_height(chain_id: &str, input: u64) {
+    let mut r = SUPPLY_HEIGHT.write().unwrap();
+    r.insert(chain_id.to_string(), input);
+}
 
-    thread::spawn(move || loop {
-        let runner = System::new();
-        runner.block_on(async move {
-            let contact = Contact::new(ALTHEA_NODE_GRPC, REQUEST_TIMEOUT, ALTHEA_PREFIX).unwrap();
-            match compute_liquid_supply(&contact, ALTHEA_DENOM.to_string()).await {
-                Ok(v) => {
-                    info!("Successfully updated supply info!");
-                    set_supply_info(v);
-                    thread::sleep(LOOP_TIME);
+/// The block height up to which the published supply info has applied account changes
+pub fn get_supply_height(chain_id: &str) -> Option<u64> {
+    SUPPLY_HEIGHT.read().unwrap().get(chain_id).copied()
+}
+
+/// Crawls every account on the chain, then keeps the supply info up to date from the accounts touched by new
+/// blocks until the next full crawl
+pub async fn chain_supply_worker(chain: &'static ChainConfig) {
+    let mut retry_delay = MIN_CRAWL_RETRY_DELAY;
+    loop {
+        let settings = get_settings();
+        let full_crawl_interval = Duration::from_secs(settings.full_supply_crawl_interval);
+        let contact = match Contact::new(chain.grpc(), REQUEST_TIMEOUT, &chain.prefix) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Invalid gRPC url for {} with {:?}", chain.chain_id, e);
+                sleep(full_crawl_interval).await;
+                continue;
+            }
+        };
+        let mut state = match crawl_accounts(&contact, chain).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!(
+                    "Failed to crawl accounts on {} with {:?}, retrying in {:?}",
+                    chain.chain_id, e, retry_delay
+                );
+                sleep(retry_delay).await;
+                retry_delay = (retry_delay * 2).min(full_crawl_interval);
+                continue;
+            }
+        };
+        retry_delay = MIN_CRAWL_RETRY_DELAY;
+        let crawled = Instant::now();
+        // the account statistics are combined with the Althea L1 transaction index
+        if is_althea(chain) {
+            update_account_stats(state.account_counts.clone());
+        }
+
+        // the full crawl doubles as a consistency check on the incremental updates made since the last one
+        let previous = get_supply_info(&chain.chain_id);
+        if let (Some(previous), Some(current)) =
+            (previous, update_supply_info(&contact, &state).await)
+        {
+            check_incremental_drift(&previous, &current, settings.supply_discrepancy_threshold);
+        }
+
+        let rpc = match &chain.tendermint_rpc {
+            Some(v) => HttpClient::new(v),
+            None => {
+                sleep(full_crawl_interval).await;
+                continue;
+            }
+        };
+        while crawled.elapsed() < full_crawl_interval {
+            sleep(Duration::from_secs(settings.supply_update_interval)).await;
+            match update_touched_accounts(&contact, &rpc, &mut state).await {
+                Ok(touched) => {
+                    info!(
+                        "Updated {} accounts touched up to block {} on {}",
+                        touched, state.height, chain.chain_id
+                    );
+                    update_supply_info(&contact, &state).await;
                 }
-                Err(e) => error!("Failed to update supply info with {:?}", e),
+                Err(e) => error!(
+                    "Failed to update touched accounts on {} with {:?}",
+                    chain.chain_id, e
+                ),
             }
-        });
-    });
+        }
+    }
+}
+
+/// Computes and publishes supply info from the current state, returning the published numbers. Suspect numbers
+/// are only published in the supply report, the previous supply info is kept for the endpoints until it is stale
+async fn update_supply_info(
+    contact: &Contact,
+    state: &SupplyState,
+) -> Option<ChainTotalSupplyNumbers> {
+    let chain_id = &state.chain.chain_id;
+    match compute_liquid_supply(contact, state).await {
+        Ok(v) => {
+            let suspect = v.report.suspect;
+            if suspect {
+                error!(
+                    "Supply info for {} is suspect, the supply categories are off by {} ({:.4}) from the total supply, keeping the previous supply info",
+                    chain_id, v.report.absolute_discrepancy, v.report.relative_discrepancy
+                );
+            }
+            set_supply_report(chain_id, v.report);
+            if suspect {
+                return None;
+            }
+            info!("Successfully updated supply info for {}!", chain_id);
+            set_module_accounts_info(chain_id, v.module_accounts);
+            set_supply_info(chain_id, v.supply.clone());
+            set_supply_height(chain_id, state.height);
+            Some(v.supply)
+        }
+        Err(e) => {
+            error!("Failed to update supply info for {} with {:?}", chain_id, e);
+            None
+        }
+    }
+}
+
+/// Logs how far the liquid supply from incremental updates had drifted from a full crawl
+fn check_incremental_drift(
+    incremental: &ChainTotalSupplyNumbers,
+    full: &ChainTotalSupplyNumbers,
+    threshold: f64,
+) {
+    let a = incremental.total_liquid_supply;
+    let b = full.total_liquid_supply;
+    let drift = ratio(if a > b { a - b } else { b - a }, b);
+    if drift > threshold {
+        error!(
+            "Incremental updates had drifted to a liquid supply of {} from the full crawl's {} ({:.4})",
+            a, b, drift
+        );
+    } else {
+        info!(
+            "Incremental updates had a liquid supply of {} against the full crawl's {} ({:.4})",
+            a, b, drift
+        );
+    }
+}
+
+/// Crawls every account on chain, this is slow so afterwards only accounts touched by new blocks are queried
+async fn crawl_accounts(
+    contact: &Contact,
+    chain: &'static ChainConfig,
+) -> Result<SupplyState, CosmosGrpcError> {
+    // events from blocks after this height are applied on top of the crawl, accounts which change during the
+    // crawl are simply queried again
+    let (height, _) = get_latest_block_info(contact).await?;
+
+    let settings = get_settings();
+    let batch_size = settings.crawl_batch_size.max(1);
+    let concurrency = settings.crawl_concurrency.max(1);
+    let pool = ClientPool::connect(&chain.grpc_urls, concurrency).await?;
+    let limiter = RateLimiter::new(settings.crawl_max_requests_per_second);
+
+    let start = Instant::now();
+    info!(
+        "Starting account crawl of {} in batches of {}, {} at a time",
+        chain.chain_id, batch_size, concurrency
+    );
+    // get every account on chain and every balance for every account
+    let users = stream_users(
+        &chain.chain_id,
+        pool.get(),
+        || pool.get(),
+        chain.native_denom.clone(),
+        batch_size,
+        concurrency,
+        &limiter,
+    )
+    .await?;
+    info!(
+        "Got all balances/vesting after {}s",
+        start.elapsed().as_secs()
+    );
+    finish_crawl_status(&chain.chain_id);
+
+    Ok(SupplyState {
+        chain,
+        height,
+        account_counts: count_accounts(users.values().map(|u| &u.account)),
+        users,
+    })
+}
+
+/// Queries every account touched by bank, staking or distribution events in blocks after the state's height again.
+/// Unclaimed rewards grow every block without an event, so for untouched accounts they are only as recent as the
+/// last full crawl or the last time the account was touched
+async fn update_touched_accounts(
+    contact: &Contact,
+    rpc: &HttpClient,
+    state: &mut SupplyState,
+) -> Result<usize, CosmosGrpcError> {
+    let (latest, _) = get_latest_block_info(contact).await?;
+    let mut touched = BTreeSet::new();
+    for height in state.height + 1..=latest {
+        let results = get_block_results(rpc, height)
+            .await
+            .map_err(CosmosGrpcError::BadResponse)?;
+        touched.extend(get_touched_accounts(&results, &state.chain.prefix));
+    }
+
+    let mut clients = QueryClients::connect(state.chain.grpc()).await?;
+    let limiter = RateLimiter::new(get_settings().crawl_max_requests_per_second);
+    for address in touched.iter() {
+        let parsed = Address::from_bech32(address.clone())
+            .map_err(|e| CosmosGrpcError::BadInput(e.to_string()))?;
+        // the account itself is queried again since vesting accounts track their delegations
+        let account = match contact.get_account_vesting_info(parsed).await {
+            Ok(v) => v,
+            Err(CosmosGrpcError::NoToken) => {
+                state.users.remove(address);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let user = merge_user_information(
+            account,
+            state.chain.native_denom.clone(),
+            &mut clients,
+            &limiter,
+        )
+        .await?;
+        state.users.insert(address.clone(), user);
+    }
+    state.height = latest;
+    Ok(touched.len())
 }
 
 /// This is extremely complicated with vesting, but people want to know
@@ -96,11 +399,17 @@ pub fn chain_total_supply_thread() {
 /// is attempted we're going to compute it all at once in this function. This function is useful
 /// for any cosmos chain using standard vesting
 ///
-/// Returns liquid supply (not including community pool, including staked but liquid tokens)
+/// Returns liquid supply (not including community pool, including staked but liquid tokens) along with module
+/// account balances from the same state
 async fn compute_liquid_supply(
     contact: &Contact,
-    denom: String,
-) -> Result<ChainTotalSupplyNumbers, CosmosGrpcError> {
+    state: &SupplyState,
+) -> Result<SupplyCrawl, CosmosGrpcError> {
+    let denom = state.chain.native_denom.clone();
+    // group policy accounts are module accounts named after their address
+    let group_account_prefix = format!("{}1", state.chain.prefix);
+    let (_, block_age) = get_latest_block_info(contact).await?;
+
     // lets do the easy totals first, grand total and communiy pool
     let totals = contact.query_total_supply().await?;
     let mut total_supply = None;
@@ -109,7 +418,12 @@ async fn compute_liquid_supply(
             total_supply = Some(i.amount);
         }
     }
-    let total_supply = total_supply.unwrap();
+    let total_supply = total_supply.ok_or_else(|| {
+        CosmosGrpcError::BadResponse(format!(
+            "{denom} is not in the total supply of {}",
+            state.chain.chain_id
+        ))
+    })?;
 
     let mut community_pool = None;
     let pool_totals = contact.query_community_pool().await?;
@@ -118,43 +432,51 @@ async fn compute_liquid_supply(
             community_pool = Some(i.amount);
         }
     }
-    let community_pool = community_pool.unwrap();
+    // an empty community pool does not list the denom at all
+    let community_pool = community_pool.unwrap_or_default();
 
     let start = Instant::now();
-    info!("Starting get all accounts");
-    // start by getting every account on chain and every balance for every account
-    let accounts = contact.get_all_accounts().await?;
-    info!("Got all accounts after {}ms", start.elapsed().as_millis());
-    let users = get_balances_for_accounts(accounts, denom.clone()).await?;
-    info!(
-        "Got all balances/vesting after {}s",
-        start.elapsed().as_secs()
-    );
-    // now that we have every account with every balance we can start computing the totals
+    // we have every account with every balance so we can start computing the totals
 
     // all the tokens that are 'liquid' including staking rewards and non-vesting staking tokens
     let mut total_liquid_supply: Uint256 = 0u8.into();
 
     let mut total_liquid_balances: Uint256 = 0u8.into();
     let mut total_unclaimed_rewards: Uint256 = 0u8.into();
+    // the truncated fractions of the unclaimed rewards counted in the liquid supply, as Dec
+    let mut counted_rewards_fractions: Uint256 = 0u8.into();
     let mut total_nonvesting_staked: Uint256 = 0u8.into();
 
     let mut total_vesting: Uint256 = 0u8.into();
     let mut total_vested: Uint256 = 0u8.into();
     let mut total_vesting_staked: Uint256 = 0u8.into();
 
-    for user in users {
+    let mut total_nonvesting_unbonding: Uint256 = 0u8.into();
+    let mut total_vesting_unbonding: Uint256 = 0u8.into();
+    // non vesting unbonding tokens held by vesting accounts, see below
+    let mut vesting_accounts_nonvesting_unbonding: Uint256 = 0u8.into();
+    // module accounts which hold tokens that are not liquid, and their native balance
+    let mut module_accounts = Vec::new();
+    // account type -> tokens those accounts contributed to the liquid and vesting totals, for the supply report
+    let mut account_categories: BTreeMap<String, Uint256> = BTreeMap::new();
+
+    for user in state.users.values() {
         //let user_address = user.account.get_base_account().address.clone();
-        match user.account {
+        let category = account_type_name(&user.account);
+        let counted_before = total_liquid_supply + total_vesting;
+        match &user.account {
             // account with no vesting, simple case, all is liquid
             AccountType::ProtoBaseAccount(_) => {
                 total_liquid_balances += user.balance;
                 total_nonvesting_staked += user.total_staked;
                 total_unclaimed_rewards += user.unclaimed_rewards;
+                total_nonvesting_unbonding += user.total_unbonding;
 
                 total_liquid_supply += user.balance;
                 total_liquid_supply += user.unclaimed_rewards;
+                counted_rewards_fractions += user.unclaimed_rewards_fraction;
                 total_liquid_supply += user.total_staked;
+                total_liquid_supply += user.total_unbonding;
             }
             // In ethermint chains ethermint accounts are module accounts
             AccountType::ModuleAccount(ma) => {
@@ -166,23 +488,30 @@ async fn compute_liquid_supply(
                     total_liquid_balances += user.balance;
                     total_nonvesting_staked += user.total_staked;
                     total_unclaimed_rewards += user.unclaimed_rewards;
+                    total_nonvesting_unbonding += user.total_unbonding;
 
                     total_liquid_supply += user.balance;
                     total_liquid_supply += user.unclaimed_rewards;
+                    counted_rewards_fractions += user.unclaimed_rewards_fraction;
                     total_liquid_supply += user.total_staked;
+                    total_liquid_supply += user.total_unbonding;
                 // this is a group module multisig account, these we can count
-                } else if ma.name.starts_with("althea1")
+                } else if ma.name.starts_with(&group_account_prefix)
                     && ma.base_account.clone().unwrap().pub_key.is_none()
                 {
                     total_liquid_balances += user.balance;
                     total_nonvesting_staked += user.total_staked;
                     total_unclaimed_rewards += user.unclaimed_rewards;
+                    total_nonvesting_unbonding += user.total_unbonding;
 
                     total_liquid_supply += user.balance;
                     total_liquid_supply += user.unclaimed_rewards;
+                    counted_rewards_fractions += user.unclaimed_rewards_fraction;
                     total_liquid_supply += user.total_staked;
+                    total_liquid_supply += user.total_unbonding;
                 } else {
-                    // this is not an evm account
+                    // this is a module account, these are not liquid but are reported separately
+                    module_accounts.push((ma.clone(), user.balance));
                 }
             }
             // account with periodic vesting, now we need to determine how much has vested then compare
@@ -190,13 +519,19 @@ async fn compute_liquid_supply(
             AccountType::PeriodicVestingAccount(account_info) => {
                 let vesting_start_time =
                     UNIX_EPOCH + Duration::from_secs(account_info.start_time as u64);
-                let base = account_info.base_vesting_account.unwrap();
+                let base = account_info.base_vesting_account.clone().unwrap();
                 // delegated vesting may be more than the remaining vesting amount if the user hasn't updated thier delegation
                 // since the last vesting event
                 let (total_delegated_free, total_delegated_vesting, original_vesting_amount) =
                     sum_vesting(base, denom.clone());
+                let (nonvesting_unbonding, vesting_unbonding) =
+                    split_unbonding(user.total_unbonding, total_delegated_free);
+                total_nonvesting_unbonding += nonvesting_unbonding;
+                vesting_accounts_nonvesting_unbonding += nonvesting_unbonding;
+                total_vesting_unbonding += vesting_unbonding;
                 // obvious stuff requiring no computation
                 total_liquid_supply += user.unclaimed_rewards;
+                counted_rewards_fractions += user.unclaimed_rewards_fraction;
 
                 // Account for tokens staked beyond original vesting (e.g., staked rewards)
                 // These are fully liquid and not tracked by the vesting module
@@ -217,7 +552,7 @@ async fn compute_liquid_supply(
                     let mut total_amount_vested: Uint256 = 0u8.into();
                     // seconds offset from vesting start time
                     let mut time_counter = 0;
-                    for vesting_period in account_info.vesting_periods {
+                    for vesting_period in &account_info.vesting_periods {
                         time_counter += vesting_period.length;
                         // if this vesting period has already elapsed, add the mount
                         if vesting_start_time + Duration::from_secs(time_counter as u64)
@@ -290,19 +625,23 @@ async fn compute_liquid_supply(
                     total_liquid_supply += user.balance - vesting_in_balance;
                 }
             }
-            AccountType::ContinuousVestingAccount(account_info) => {
-                let vesting_start_time =
-                    UNIX_EPOCH + Duration::from_secs(account_info.start_time as u64);
-                let base = account_info.base_vesting_account.unwrap();
-                assert!(base.end_time > account_info.start_time);
-                let vesting_duration =
-                    Duration::from_secs(base.end_time as u64 - account_info.start_time as u64);
+            AccountType::ContinuousVestingAccount(_) | AccountType::DelayedVestingAccount(_) => {
+                let (base, start_time) =
+                    continuous_schedule(&user.account).map_err(CosmosGrpcError::BadResponse)?;
+                let vesting_start_time = UNIX_EPOCH + Duration::from_secs(start_time as u64);
+                let vesting_duration = Duration::from_secs((base.end_time - start_time) as u64);
                 let (total_delegated_free, total_delegated_vesting, original_vesting_amount) =
                     sum_vesting(base, denom.clone());
+                let (nonvesting_unbonding, vesting_unbonding) =
+                    split_unbonding(user.total_unbonding, total_delegated_free);
+                total_nonvesting_unbonding += nonvesting_unbonding;
+                vesting_accounts_nonvesting_unbonding += nonvesting_unbonding;
+                total_vesting_unbonding += vesting_unbonding;
 
                 // obvious stuff requiring no computation
                 total_unclaimed_rewards += user.unclaimed_rewards;
                 total_liquid_supply += user.unclaimed_rewards;
+                counted_rewards_fractions += user.unclaimed_rewards_fraction;
                 total_liquid_supply += total_delegated_free;
                 total_vesting_staked += total_delegated_vesting;
                 total_nonvesting_staked += total_delegated_free;
@@ -312,19 +651,13 @@ async fn compute_liquid_supply(
                 // and grant that as liquid
                 if vesting_start_time < SystemTime::now() {
                     let elapsed_since_vesting_started = vesting_start_time.elapsed().unwrap();
-                    let vesting_percent_complete = elapsed_since_vesting_started.as_secs() as f64
-                        / vesting_duration.as_secs() as f64;
-
-                    if vesting_percent_complete > 1.0 {
-                        total_liquid_supply += user.balance;
-                        continue;
-                    }
-
-                    let original_vesting_amount_float: f64 =
-                        original_vesting_amount.to_string().parse().unwrap();
-                    let total_amount_vested: f64 =
-                        original_vesting_amount_float * vesting_percent_complete;
-                    let total_amount_vested: Uint256 = (total_amount_vested.ceil() as u128).into();
+                    // a fully vested account falls through with nothing still vesting, so that it is still
+                    // counted in the vested total and its category
+                    let total_amount_vested = continuous_vested(
+                        original_vesting_amount,
+                        elapsed_since_vesting_started.as_secs(),
+                        vesting_duration.as_secs(),
+                    );
 
                     assert!(original_vesting_amount >= total_amount_vested);
                     let total_amount_still_vesting = original_vesting_amount - total_amount_vested;
@@ -363,83 +696,377 @@ async fn compute_liquid_supply(
                     total_liquid_supply += liquid;
                 }
             }
-            AccountType::DelayedVestingAccount(_) => todo!(),
             // it's locked, not liquid
             AccountType::PermenantLockedAccount(_) => {}
         }
+        *account_categories
+            .entry(category.to_string())
+            .or_insert(0u8.into()) += total_liquid_supply + total_vesting - counted_before;
     }
 
-    info!("Finishes totals after {}s", start.elapsed().as_secs());
-    Ok(ChainTotalSupplyNumbers {
+    // The vesting module tracks unbonding tokens as delegated until unbonding completes, so for vesting accounts
+    // the delegated free amount added to the liquid supply above already includes non-vesting unbonding tokens.
+    // They do however need to be removed from the staked totals, which for vesting accounts come from the same
+    // delegated amounts
+    total_nonvesting_staked = saturating_sub(
+        total_nonvesting_staked,
+        vesting_accounts_nonvesting_unbonding,
+    );
+    total_vesting_staked = saturating_sub(total_vesting_staked, total_vesting_unbonding);
+
+    let rounding = get_settings().rewards_rounding;
+    if rounding == RewardsRounding::Total {
+        // the per account amounts above are truncated, the whole tokens their fractions add up to are missing
+        let fractions = counted_rewards_fractions / DEC_ONE.into();
+        total_unclaimed_rewards += fractions;
+        total_liquid_supply += fractions;
+    }
+
+    // commission is withdrawn to the operator's account where it is liquid, vesting or not
+    let mut total_unclaimed_commission: Uint256 = 0u8.into();
+    let mut commission_fractions: Uint256 = 0u8.into();
+    for commission in get_validator_commissions(state.chain).await? {
+        let amount = commission.amount / DEC_ONE.into();
+        total_unclaimed_commission += amount;
+        commission_fractions += commission.amount % DEC_ONE.into();
+        let category = state
+            .users
+            .get(&commission.operator_account)
+            .map(|u| account_type_name(&u.account))
+            .unwrap_or("base");
+        *account_categories
+            .entry(category.to_string())
+            .or_insert(0u8.into()) += amount;
+    }
+    if rounding == RewardsRounding::Total {
+        total_unclaimed_commission += commission_fractions / DEC_ONE.into();
+    }
+    total_liquid_supply += total_unclaimed_commission;
+
+    let supply = ChainTotalSupplyNumbers {
         total_liquid_supply,
         total_liquid_balances,
         total_unclaimed_rewards,
+        total_unclaimed_other_rewards: total_other_rewards(state.users.values(), rounding),
+        total_unclaimed_commission,
         total_nonvesting_staked,
         total_vesting,
         total_vesting_staked,
         total_vested,
         total_supply,
         community_pool,
+        total_unbonding: total_nonvesting_unbonding + total_vesting_unbonding,
+        total_nonvesting_unbonding,
+        total_vesting_unbonding,
+        timestamp: get_current_timestamp(),
+        block_age,
+    };
+
+    let module_accounts = get_module_account_info(state.chain, module_accounts).await?;
+    let reconciliation = reconcile_supply(&supply, &module_accounts);
+    let report = build_supply_report(
+        &reconciliation,
+        account_categories,
+        get_settings().supply_discrepancy_threshold,
+        supply.timestamp,
+    );
+
+    info!("Finishes totals after {}s", start.elapsed().as_secs());
+    Ok(SupplyCrawl {
+        supply,
+        module_accounts: ModuleAccountsInfo {
+            module_accounts,
+            reconciliation,
+        },
+        report,
     })
 }
 
-/// Dispatching utility function for building an array of joinable futures containing sets of batch requests
-async fn get_balances_for_accounts(
-    input: Vec<AccountType>,
-    denom: String,
-) -> Result<Vec<UserInfo>, CosmosGrpcError> {
-    // handed tuned parameter for the ideal number of queryes per BankQueryClient
-    const BATCH_SIZE: usize = 500;
-    info!(
-        "Querying {} accounts in {} batches of {}",
-        input.len(),
-        input.len() / BATCH_SIZE,
-        BATCH_SIZE
-    );
-    let mut index = 0;
-    let mut futs = Vec::new();
-    while index + BATCH_SIZE < input.len() - 1 {
-        futs.push(batch_query_user_information(
-            &input[index..index + BATCH_SIZE],
-            denom.clone(),
+/// A validator's unclaimed commission in the native token
+struct ValidatorCommission {
+    /// The account the commission is withdrawn to
+    operator_account: String,
+    /// As a Dec
+    amount: Uint256,
+}
+
+async fn get_validator_commissions(
+    chain: &ChainConfig,
+) -> Result<Vec<ValidatorCommission>, CosmosGrpcError> {
+    let mut clients = QueryClients::connect(chain.grpc()).await?;
+    let validators = clients
+        .staking
+        .validators(QueryValidatorsRequest {
+            status: String::new(),
+            pagination: PAGE,
+        })
+        .await?
+        .into_inner()
+        .validators;
+
+    let mut commissions = Vec::new();
+    for v in validators {
+        let commission = clients
+            .dist
+            .validator_commission(QueryValidatorCommissionRequest {
+                validator_address: v.operator_address.clone(),
+            })
+            .await?
+            .into_inner()
+            .commission
+            .map(|c| c.commission)
+            .unwrap_or_default();
+        let mut amount: Uint256 = 0u8.into();
+        for coin in commission {
+            if coin.denom == chain.native_denom {
+                amount += parse_dec(&coin.amount).map_err(CosmosGrpcError::BadResponse)?;
+            }
+        }
+        let operator_account = Address::from_bech32(v.operator_address)
+            .and_then(|a| a.to_bech32(chain.prefix.clone()))
+            .map_err(|e| CosmosGrpcError::BadResponse(format!("{e:?}")))?;
+        commissions.push(ValidatorCommission {
+            operator_account,
+            amount,
+        });
+    }
+    Ok(commissions)
+}
+
+/// Totals unclaimed rewards in tokens other than the native token, truncating to whole tokens according to the
+/// rounding policy
+fn total_other_rewards<'a>(
+    users: impl IntoIterator<Item = &'a UserInfo>,
+    rounding: RewardsRounding,
+) -> BTreeMap<String, Uint256> {
+    let mut totals: BTreeMap<String, Uint256> = BTreeMap::new();
+    for user in users {
+        for (denom, amount) in user.other_rewards.iter() {
+            let amount = match rounding {
+                RewardsRounding::PerAccount => *amount / DEC_ONE.into(),
+                RewardsRounding::Total => *amount,
+            };
+            *totals.entry(denom.clone()).or_default() += amount;
+        }
+    }
+    if rounding == RewardsRounding::Total {
+        for amount in totals.values_mut() {
+            *amount /= DEC_ONE.into();
+        }
+    }
+    totals
+}
+
+/// Parses a Dec into an integer with 18 implied decimal places. Over gRPC Decs are sent in this integer form, but
+/// the decimal form is accepted as well
+fn parse_dec(input: &str) -> Result<Uint256, String> {
+    let parse = |v: &str| -> Result<Uint256, String> {
+        if v.is_empty() || !v.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("Invalid Dec {input}"));
+        }
+        v.parse().map_err(|_| format!("Invalid Dec {input}"))
+    };
+    match input.split_once('.') {
+        None => parse(input),
+        Some((whole, fraction)) => {
+            if fraction.len() > 18 {
+                return Err(format!("Dec {input} has more than 18 decimal places"));
+            }
+            let fraction = format!("{fraction:0<18}");
+            Ok(parse(whole)? * DEC_ONE.into() + parse(&fraction)?)
+        }
+    }
+}
+
+/// Returns the height of the node's latest block and how many seconds behind the wall clock it is
+async fn get_latest_block_info(contact: &Contact) -> Result<(u64, u64), CosmosGrpcError> {
+    let block = match contact.get_latest_block().await? {
+        LatestBlock::Latest { block } | LatestBlock::Syncing { block } => block,
+        LatestBlock::WaitingToStart => {
+            return Err(CosmosGrpcError::BadResponse(
+                "Node is waiting for the chain to start".to_string(),
+            ))
+        }
+    };
+    let header = block
+        .header
+        .ok_or_else(|| CosmosGrpcError::BadResponse("Block without header".to_string()))?;
+    let block_time = header
+        .time
+        .map(|t| t.seconds as u64)
+        .ok_or_else(|| CosmosGrpcError::BadResponse("Block without time".to_string()))?;
+    Ok((
+        header.height as u64,
+        get_current_timestamp().saturating_sub(block_time),
+    ))
+}
+
+/// Checks that supply numbers are recent enough to serve, returning the reason if they are not
+pub fn check_supply_freshness(
+    supply: &ChainTotalSupplyNumbers,
+    now: u64,
+    max_block_age: u64,
+    max_supply_age: u64,
+) -> Result<(), String> {
+    if supply.block_age > max_block_age {
+        return Err(format!(
+            "Supply data is stale, the fullnode's latest block was {} seconds old when it was computed, the limit is {} seconds",
+            supply.block_age, max_block_age
         ));
-        index += BATCH_SIZE;
     }
-    futs.push(batch_query_user_information(&input[index..], denom.clone()));
+    let age = now.saturating_sub(supply.timestamp);
+    if age > max_supply_age {
+        return Err(format!(
+            "Supply data is stale, it was computed {age} seconds ago, the limit is {max_supply_age} seconds"
+        ));
+    }
+    Ok(())
+}
 
-    let executed_futures = join_all(futs).await;
-    let mut balances = Vec::new();
-    for b in executed_futures {
-        balances.extend(b?);
+/// The queries the account crawl makes, the crawl is generic over these so that it can be tested against a mock node
+trait SupplyNode {
+    /// Returns the page of accounts starting at `key`, with the total number of accounts if `count_total` is set
+    async fn get_accounts_page(
+        &mut self,
+        key: Vec<u8>,
+        count_total: bool,
+    ) -> Result<AccountsPage, CosmosGrpcError>;
+
+    async fn get_user_info(
+        &mut self,
+        account: AccountType,
+        denom: String,
+        limiter: &RateLimiter,
+    ) -> Result<UserInfo, CosmosGrpcError>;
+}
+
+struct AccountsPage {
+    accounts: Vec<AccountType>,
+    /// The key of the next page, empty if this is the last page
+    next_key: Vec<u8>,
+    total: Option<u64>,
+}
+
+impl SupplyNode for QueryClients {
+    async fn get_accounts_page(
+        &mut self,
+        key: Vec<u8>,
+        count_total: bool,
+    ) -> Result<AccountsPage, CosmosGrpcError> {
+        let res = self
+            .auth
+            .accounts(QueryAccountsRequest {
+                pagination: Some(PageRequest {
+                    key,
+                    offset: 0,
+                    limit: ACCOUNTS_PAGE_SIZE,
+                    count_total,
+                    reverse: false,
+                }),
+            })
+            .await?
+            .into_inner();
+        let mut accounts = Vec::new();
+        for value in res.accounts {
+            accounts.push(AccountType::decode_from_any(value)?);
+        }
+        let (next_key, total) = match res.pagination {
+            Some(p) => (p.next_key, count_total.then_some(p.total)),
+            None => (Vec::new(), None),
+        };
+        Ok(AccountsPage {
+            accounts,
+            next_key,
+            total,
+        })
+    }
+
+    async fn get_user_info(
+        &mut self,
+        account: AccountType,
+        denom: String,
+        limiter: &RateLimiter,
+    ) -> Result<UserInfo, CosmosGrpcError> {
+        merge_user_information(account, denom, self, limiter).await
     }
-    Ok(balances)
 }
 
-/// Utility function for batching balance requests so that they occupy a single bankqueryclient which represents a connection
-/// to the rpc server, opening connections is overhead intensive so we want to do a few thousand requests per client to really
-/// make it worth our while
-async fn batch_query_user_information(
-    input: &[AccountType],
+/// Queries every account on chain as a pipeline, each page of accounts is split into batches which are queried
+/// `concurrency` at a time, each over a connection from `get_node`, while the next page is fetched. This bounds
+/// the raw pages and queries in flight, but the returned map still holds a UserInfo for every account since the
+/// SupplyState built from it is updated per account afterwards
+async fn stream_users<N: SupplyNode>(
+    chain_id: &str,
+    pager: N,
+    get_node: impl Fn() -> N,
     denom: String,
+    batch_size: usize,
+    concurrency: usize,
+    limiter: &RateLimiter,
+) -> Result<BTreeMap<String, UserInfo>, CosmosGrpcError> {
+    start_crawl_status(chain_id, 0);
+    let pages = stream::try_unfold((pager, Some(Vec::new())), |(mut pager, key)| async move {
+        let key = match key {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        // only the first page has an empty key
+        let page = pager.get_accounts_page(key.clone(), key.is_empty()).await?;
+        if let Some(total) = page.total {
+            set_crawl_total(chain_id, total);
+        }
+        let next_key = if page.next_key.is_empty() {
+            None
+        } else {
+            Some(page.next_key)
+        };
+        Ok::<_, CosmosGrpcError>(Some((page.accounts, (pager, next_key))))
+    });
+    let batches = pages
+        .map_ok(|accounts| stream::iter(split_batches(accounts, batch_size).into_iter().map(Ok)))
+        .try_flatten()
+        .map_ok(|batch| {
+            batch_query_user_information(chain_id, batch, denom.clone(), get_node(), limiter)
+        })
+        .try_buffer_unordered(concurrency);
+    let mut batches = pin!(batches);
+
+    let mut users = BTreeMap::new();
+    while let Some(batch) = batches.try_next().await? {
+        for user in batch {
+            users.insert(user.account.get_base_account().address.to_string(), user);
+        }
+    }
+    Ok(users)
+}
+
+/// Splits a list into batches of at most `batch_size`
+fn split_batches<T>(input: Vec<T>, batch_size: usize) -> Vec<Vec<T>> {
+    let mut input = input.into_iter().peekable();
+    let mut batches = Vec::new();
+    while input.peek().is_some() {
+        batches.push(input.by_ref().take(batch_size).collect());
+    }
+    batches
+}
+
+/// Utility function for batching balance requests so that they occupy a single connection from the pool for a few
+/// hundred accounts at a time
+async fn batch_query_user_information<N: SupplyNode>(
+    chain_id: &str,
+    input: Vec<AccountType>,
+    denom: String,
+    mut node: N,
+    limiter: &RateLimiter,
 ) -> Result<Vec<UserInfo>, CosmosGrpcError> {
     trace!("Starting batch of {}", input.len());
-    let mut bankrpc = BankQueryClient::connect(ALTHEA_NODE_GRPC).await?;
-    let mut distrpc = DistQueryClient::connect(ALTHEA_NODE_GRPC).await?;
-    let mut stakingrpc = StakingQueryClient::connect(ALTHEA_NODE_GRPC).await?;
-
+    let count = input.len() as u64;
     let mut ret = Vec::new();
     for account in input {
-        let res = merge_user_information(
-            account.clone(),
-            denom.clone(),
-            &mut bankrpc,
-            &mut distrpc,
-            &mut stakingrpc,
-        )
-        .await?;
-        ret.push(res);
+        ret.push(node.get_user_info(account, denom.clone(), limiter).await?);
     }
-    trace!("Finished batch of {}", input.len());
+    trace!("Finished batch of {}", count);
+    record_crawl_progress(chain_id, count);
     Ok(ret)
 }
 
@@ -448,33 +1075,50 @@ async fn batch_query_user_information(
 async fn merge_user_information(
     account: AccountType,
     denom: String,
-    bankrpc: &mut BankQueryClient<Channel>,
-    distrpc: &mut DistQueryClient<Channel>,
-    stakingrpc: &mut StakingQueryClient<Channel>,
+    clients: &mut QueryClients,
+    limiter: &RateLimiter,
 ) -> Result<UserInfo, CosmosGrpcError> {
-    // required because dec coins are multiplied by 1*10^18
-    const ONE_ETH: u128 = 10u128.pow(18);
-
     let address = account.get_base_account().address;
-    let balance_fut = bankrpc.balance(QueryBalanceRequest {
+    // one request for each of the four queries below
+    limiter.acquire(4).await;
+    let balance_fut = clients.bank.balance(QueryBalanceRequest {
         address: address.to_string(),
         denom: denom.clone(),
     });
     let delegation_rewards_fut =
-        distrpc.delegation_total_rewards(QueryDelegationTotalRewardsRequest {
-            delegator_address: address.to_string(),
+        clients
+            .dist
+            .delegation_total_rewards(QueryDelegationTotalRewardsRequest {
+                delegator_address: address.to_string(),
+            });
+    // tonic clients are cheap to clone and share the underlying connection, this lets us run both
+    // staking queries at once
+    let mut unbondingrpc = clients.staking.clone();
+    let total_delegated_fut =
+        clients
+            .staking
+            .delegator_delegations(QueryDelegatorDelegationsRequest {
+                delegator_addr: address.to_string(),
+                pagination: PAGE,
+            });
+    let total_unbonding_fut =
+        unbondingrpc.delegator_unbonding_delegations(QueryDelegatorUnbondingDelegationsRequest {
+            delegator_addr: address.to_string(),
+            pagination: PAGE,
         });
-    let total_delegated_fut = stakingrpc.delegator_delegations(QueryDelegatorDelegationsRequest {
-        delegator_addr: address.to_string(),
-        pagination: PAGE,
-    });
 
-    let (balance, delegation_rewards, total_delegated) =
-        join3(balance_fut, delegation_rewards_fut, total_delegated_fut).await;
+    let (balance, delegation_rewards, total_delegated, total_unbonding) = join4(
+        balance_fut,
+        delegation_rewards_fut,
+        total_delegated_fut,
+        total_unbonding_fut,
+    )
+    .await;
 
     let balance = balance?.into_inner();
     let delegation_rewards = delegation_rewards?.into_inner();
     let delegated = total_delegated?.into_inner();
+    let unbonding = total_unbonding?.into_inner();
 
     let balance = match balance.balance {
         Some(v) => {
@@ -485,13 +1129,15 @@ async fn merge_user_information(
     };
 
     let mut delegation_rewards_total: Uint256 = 0u8.into();
+    let mut other_rewards: BTreeMap<String, Uint256> = BTreeMap::new();
     for reward in delegation_rewards.total {
+        let amount = parse_dec(&reward.amount).map_err(CosmosGrpcError::BadResponse)?;
         if reward.denom == denom {
-            delegation_rewards_total += reward.amount.parse().unwrap();
+            delegation_rewards_total += amount;
+        } else {
+            *other_rewards.entry(reward.denom).or_default() += amount;
         }
-        // you can total non-native token rewards in an else case here
     }
-    delegation_rewards_total /= ONE_ETH.into();
 
     let mut total_delegated: Uint256 = 0u8.into();
     for delegated in delegated.delegation_responses {
@@ -502,11 +1148,17 @@ async fn merge_user_information(
         }
     }
 
+    let total_unbonding =
+        sum_unbonding(&unbonding.unbonding_responses).map_err(CosmosGrpcError::BadResponse)?;
+
     Ok(UserInfo {
         account,
         balance,
-        unclaimed_rewards: delegation_rewards_total,
+        unclaimed_rewards: delegation_rewards_total / DEC_ONE.into(),
+        unclaimed_rewards_fraction: delegation_rewards_total % DEC_ONE.into(),
+        other_rewards,
         total_staked: total_delegated,
+        total_unbonding,
     })
 }
 
@@ -534,27 +1186,407 @@ fn sum_vesting(input: BaseVestingAccount, denom: String) -> (Uint256, Uint256, U
     (total_free, total_vesting, original_amount)
 }
 
+/// The base vesting account and vesting start time of a continuous or delayed vesting account. A delayed vesting
+/// account is a continuous one with a zero length duration, everything is locked until its end time and vested after
+fn continuous_schedule(account: &AccountType) -> Result<(BaseVestingAccount, i64), String> {
+    let (base, start_time) = match account {
+        AccountType::ContinuousVestingAccount(a) => {
+            (a.base_vesting_account.clone(), Some(a.start_time))
+        }
+        AccountType::DelayedVestingAccount(a) => (a.base_vesting_account.clone(), None),
+        _ => return Err(format!("{} is not continuous", account_type_name(account))),
+    };
+    let base = base.ok_or_else(|| "Vesting account without a base vesting account".to_string())?;
+    let address = base
+        .base_account
+        .as_ref()
+        .map_or("", |a| a.address.as_str());
+    let start_time = start_time.unwrap_or(base.end_time);
+    if start_time < 0 || base.end_time < start_time {
+        return Err(format!(
+            "Vesting account {address} has an invalid schedule from {start_time} to {}",
+            base.end_time
+        ));
+    }
+    Ok((base, start_time))
+}
+
+/// The amount of a continuous vesting account vested `elapsed` seconds into its vesting `duration`, rounded up
+fn continuous_vested(original_vesting_amount: Uint256, elapsed: u64, duration: u64) -> Uint256 {
+    if elapsed >= duration {
+        return original_vesting_amount;
+    }
+    let original_vesting_amount_float: f64 = original_vesting_amount.to_string().parse().unwrap();
+    let vested = original_vesting_amount_float * elapsed as f64 / duration as f64;
+    // the float may round above the original amount
+    std::cmp::min((vested.ceil() as u128).into(), original_vesting_amount)
+}
+
+/// Sums the balance of every entry of a delegator's unbonding delegations
+fn sum_unbonding(unbonding: &[UnbondingDelegation]) -> Result<Uint256, String> {
+    let mut total: Uint256 = 0u8.into();
+    for entry in unbonding.iter().flat_map(|u| u.entries.iter()) {
+        total += entry
+            .balance
+            .parse()
+            .map_err(|_| format!("Invalid unbonding balance {}", entry.balance))?;
+    }
+    Ok(total)
+}
+
+/// Splits a vesting account's unbonding tokens into (non vesting, vesting). When unbonding completes the
+/// vesting module releases delegated free tokens first, so unbonding tokens are non vesting up to the
+/// delegated free amount and vesting beyond that
+fn split_unbonding(unbonding: Uint256, delegated_free: Uint256) -> (Uint256, Uint256) {
+    if unbonding > delegated_free {
+        (delegated_free, unbonding - delegated_free)
+    } else {
+        (unbonding, 0u8.into())
+    }
+}
+
+fn saturating_sub(a: Uint256, b: Uint256) -> Uint256 {
+    if a > b {
+        a - b
+    } else {
+        0u8.into()
+    }
+}
+
 #[derive(Debug, Clone)]
 struct UserInfo {
     account: AccountType,
     balance: Uint256,
+    /// Unclaimed rewards in whole tokens, truncated
     unclaimed_rewards: Uint256,
+    /// The fraction of a token truncated from unclaimed_rewards, as a Dec
+    unclaimed_rewards_fraction: Uint256,
+    /// Denom -> unclaimed rewards in other tokens, as Decs
+    other_rewards: BTreeMap<String, Uint256>,
     total_staked: Uint256,
+    total_unbonding: Uint256,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chains::{get_chain, ALTHEA_CHAIN_ID};
+    use crate::crawl::get_crawl_status;
+    use crate::settings::Settings;
+    use crate::ALTHEA_PREFIX;
+    use cosmos_sdk_proto_althea::cosmos::auth::v1beta1::BaseAccount;
     use std::cmp::{max, min};
+    use std::sync::Mutex;
+
+    /// An in memory node which records every account queried
+    #[derive(Clone)]
+    struct MockNode {
+        accounts: Arc<Vec<AccountType>>,
+        page_size: usize,
+        queried: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl SupplyNode for MockNode {
+        async fn get_accounts_page(
+            &mut self,
+            key: Vec<u8>,
+            count_total: bool,
+        ) -> Result<AccountsPage, CosmosGrpcError> {
+            let start = if key.is_empty() {
+                0
+            } else {
+                u64::from_be_bytes(key.try_into().unwrap()) as usize
+            };
+            let end = (start + self.page_size).min(self.accounts.len());
+            Ok(AccountsPage {
+                accounts: self.accounts[start..end].to_vec(),
+                next_key: if end < self.accounts.len() {
+                    (end as u64).to_be_bytes().to_vec()
+                } else {
+                    Vec::new()
+                },
+                total: count_total.then_some(self.accounts.len() as u64),
+            })
+        }
+
+        async fn get_user_info(
+            &mut self,
+            account: AccountType,
+            _denom: String,
+            limiter: &RateLimiter,
+        ) -> Result<UserInfo, CosmosGrpcError> {
+            limiter.acquire(4).await;
+            self.queried
+                .lock()
+                .unwrap()
+                .push(account.get_base_account().address.to_string());
+            Ok(UserInfo {
+                account,
+                balance: 1u8.into(),
+                unclaimed_rewards: 0u8.into(),
+                unclaimed_rewards_fraction: 0u8.into(),
+                other_rewards: BTreeMap::new(),
+                total_staked: 0u8.into(),
+                total_unbonding: 0u8.into(),
+            })
+        }
+    }
+
+    fn mock_account(number: u64) -> AccountType {
+        let mut bytes = [0u8; 20];
+        bytes[..8].copy_from_slice(&number.to_be_bytes());
+        AccountType::ProtoBaseAccount(BaseAccount {
+            address: Address::from_slice(&bytes, ALTHEA_PREFIX)
+                .unwrap()
+                .to_bech32(ALTHEA_PREFIX)
+                .unwrap(),
+            pub_key: None,
+            account_number: number,
+            sequence: 0,
+        })
+    }
+
+    #[actix_web::test]
+    async fn test_stream_users() {
+        let batch_size = Settings::default().crawl_batch_size;
+        for count in [0, 1, batch_size, batch_size + 1] {
+            let node = MockNode {
+                accounts: Arc::new((0..count as u64).map(mock_account).collect()),
+                // pages which don't line up with batches
+                page_size: 300,
+                queried: Arc::new(Mutex::new(Vec::new())),
+            };
+            let users = stream_users(
+                "mock",
+                node.clone(),
+                || node.clone(),
+                ALTHEA_DENOM.to_string(),
+                batch_size,
+                3,
+                &RateLimiter::new(0),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(users.len(), count);
+            let mut queried = node.queried.lock().unwrap().clone();
+            queried.sort();
+            queried.dedup();
+            assert_eq!(queried.len(), count);
+            let counts = count_accounts(users.values().map(|u| &u.account));
+            assert_eq!(counts.total_accounts, count as u64);
+            let status = get_crawl_status("mock").unwrap();
+            assert_eq!(status.accounts_done, count as u64);
+            assert_eq!(status.accounts_total, count as u64);
+        }
+    }
+
+    #[test]
+    fn test_parse_dec() {
+        let one: Uint256 = DEC_ONE.into();
+        assert_eq!(
+            parse_dec("1500000000000000000").unwrap(),
+            one + one / 2u8.into()
+        );
+        assert_eq!(parse_dec("1.5").unwrap(), one + one / 2u8.into());
+        assert_eq!(parse_dec("0.000000000000000001").unwrap(), 1u8.into());
+        assert!(parse_dec("0.0000000000000000001").is_err());
+        assert!(parse_dec("1e18").is_err());
+        assert!(parse_dec("-1").is_err());
+        assert!(parse_dec("").is_err());
+    }
+
+    #[test]
+    fn test_total_other_rewards() {
+        let user = |amount: &str| UserInfo {
+            account: mock_account(0),
+            balance: 0u8.into(),
+            unclaimed_rewards: 0u8.into(),
+            unclaimed_rewards_fraction: 0u8.into(),
+            other_rewards: [("ibc/USDC".to_string(), parse_dec(amount).unwrap())].into(),
+            total_staked: 0u8.into(),
+            total_unbonding: 0u8.into(),
+        };
+        let users = [user("1.6"), user("2.6")];
+        assert_eq!(
+            total_other_rewards(&users, RewardsRounding::PerAccount)["ibc/USDC"],
+            3u8.into()
+        );
+        assert_eq!(
+            total_other_rewards(&users, RewardsRounding::Total)["ibc/USDC"],
+            4u8.into()
+        );
+    }
+
+    fn zero_supply(timestamp: u64, block_age: u64) -> ChainTotalSupplyNumbers {
+        let zero: Uint256 = 0u8.into();
+        ChainTotalSupplyNumbers {
+            total_supply: zero,
+            community_pool: zero,
+            total_liquid_supply: zero,
+            total_liquid_balances: zero,
+            total_unclaimed_rewards: zero,
+            total_unclaimed_other_rewards: BTreeMap::new(),
+            total_unclaimed_commission: zero,
+            total_nonvesting_staked: zero,
+            total_vesting: zero,
+            total_vesting_staked: zero,
+            total_vested: zero,
+            total_unbonding: zero,
+            total_nonvesting_unbonding: zero,
+            total_vesting_unbonding: zero,
+            timestamp,
+            block_age,
+        }
+    }
+
+    #[test]
+    fn test_should_publish_supply_event() {
+        let supply = |total_supply: u32, timestamp: u64, block_age: u64| ChainTotalSupplyNumbers {
+            total_supply: total_supply.into(),
+            ..zero_supply(timestamp, block_age)
+        };
+        let previous = supply(100, 1000, 5);
+        assert!(should_publish_supply_event(None, &previous, 600));
+        // only the timestamps differ
+        assert!(!should_publish_supply_event(
+            Some(&previous),
+            &supply(100, 1060, 6),
+            600
+        ));
+        assert!(should_publish_supply_event(
+            Some(&previous),
+            &supply(101, 1060, 6),
+            600
+        ));
+        // computed from a node that had fallen behind
+        assert!(!should_publish_supply_event(
+            Some(&previous),
+            &supply(101, 1060, 700),
+            600
+        ));
+    }
+
+    #[test]
+    fn test_continuous_vested() {
+        assert_eq!(continuous_vested(1000u32.into(), 250, 1000), 250u32.into());
+        assert_eq!(continuous_vested(1000u32.into(), 1, 3), 334u32.into());
+        // fully vested accounts vest exactly their original amount, however far past the end
+        let original: Uint256 = "123456789123456789123456789".parse().unwrap();
+        assert_eq!(continuous_vested(original, 1000, 1000), original);
+        assert_eq!(continuous_vested(original, 5000, 1000), original);
+        // and a nearly vested account never more than that
+        assert!(continuous_vested(original, 1 << 53, (1 << 53) + 1) <= original);
+    }
+
+    #[test]
+    fn test_continuous_schedule() {
+        use cosmos_sdk_proto_althea::cosmos::vesting::v1beta1::{
+            ContinuousVestingAccount, DelayedVestingAccount,
+        };
+
+        let base = BaseVestingAccount {
+            end_time: 2000,
+            ..Default::default()
+        };
+        let continuous = AccountType::ContinuousVestingAccount(ContinuousVestingAccount {
+            base_vesting_account: Some(base.clone()),
+            start_time: 1000,
+        });
+        assert_eq!(
+            continuous_schedule(&continuous).unwrap(),
+            (base.clone(), 1000)
+        );
+
+        // a delayed account vests everything at its end time
+        let delayed = AccountType::DelayedVestingAccount(DelayedVestingAccount {
+            base_vesting_account: Some(base.clone()),
+        });
+        let (_, start_time) = continuous_schedule(&delayed).unwrap();
+        assert_eq!(start_time, base.end_time);
+        let original: Uint256 = 1000u32.into();
+        assert_eq!(continuous_vested(original, 0, 0), original);
+
+        assert!(
+            continuous_schedule(&AccountType::DelayedVestingAccount(DelayedVestingAccount {
+                base_vesting_account: None
+            }))
+            .is_err()
+        );
+        assert!(continuous_schedule(&AccountType::ContinuousVestingAccount(
+            ContinuousVestingAccount {
+                base_vesting_account: Some(base),
+                start_time: 3000,
+            }
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_unbonding() {
+        use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::UnbondingDelegationEntry;
+
+        let entry = |balance: &str| UnbondingDelegationEntry {
+            balance: balance.to_string(),
+            ..Default::default()
+        };
+        let mut unbonding = vec![
+            UnbondingDelegation {
+                entries: vec![entry("10"), entry("5")],
+                ..Default::default()
+            },
+            UnbondingDelegation {
+                entries: vec![entry("20")],
+                ..Default::default()
+            },
+        ];
+        let total = sum_unbonding(&unbonding).unwrap();
+        assert_eq!(total, 35u8.into());
+        assert_eq!(
+            split_unbonding(total, 30u8.into()),
+            (30u8.into(), 5u8.into())
+        );
+        assert_eq!(
+            split_unbonding(total, 40u8.into()),
+            (35u8.into(), 0u8.into())
+        );
+
+        // a malformed balance fails the account rather than panicking the worker
+        unbonding[1].entries.push(entry("1.5"));
+        assert_eq!(
+            sum_unbonding(&unbonding),
+            Err("Invalid unbonding balance 1.5".to_string())
+        );
+    }
+
+    #[test]
+    fn test_split_batches() {
+        assert!(split_batches(Vec::<u8>::new(), 2).is_empty());
+        assert_eq!(split_batches(vec![1, 2, 3], 2), vec![vec![1, 2], vec![3]]);
+        assert_eq!(split_batches(vec![1, 2], 2), vec![vec![1, 2]]);
+    }
+
+    #[test]
+    fn test_check_supply_freshness() {
+        let supply = zero_supply(1000, 30);
+        assert!(check_supply_freshness(&supply, 1500, 60, 600).is_ok());
+        let err = check_supply_freshness(&supply, 1500, 10, 600).unwrap_err();
+        assert!(err.contains("30 seconds old"));
+        let err = check_supply_freshness(&supply, 2000, 60, 600).unwrap_err();
+        assert!(err.contains("1000 seconds ago"));
+    }
 
     /// Test the vesting query and ensure a sane result, if the total supply is off by more than 1% we have a problem, in general these don't add up
     /// exactly becuase things are updated at different times and in slightly different ways. But they shoudl always be close
     #[actix_web::test]
     async fn test_vesting_query() {
-        let contact = Contact::new(ALTHEA_NODE_GRPC, REQUEST_TIMEOUT, ALTHEA_PREFIX).unwrap();
-        let supply = compute_liquid_supply(&contact, ALTHEA_DENOM.to_string())
+        let chain = get_chain(ALTHEA_CHAIN_ID).unwrap();
+        let contact = Contact::new(chain.grpc(), REQUEST_TIMEOUT, &chain.prefix).unwrap();
+        let state = crawl_accounts(&contact, chain).await.unwrap();
+        let supply = compute_liquid_supply(&contact, &state)
             .await
-            .unwrap();
+            .unwrap()
+            .supply;
         let total = supply.community_pool + supply.total_liquid_supply + supply.total_vesting;
         let one_hundreth_of_total = supply.total_supply / 50u8.into();
         let bigger = max(total, supply.total_supply);
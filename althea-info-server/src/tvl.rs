@@ -1,26 +1,60 @@
+use clarity::Address;
 use cosmos_sdk_proto_althea::cosmos::{
-    bank::v1beta1::{query_client::QueryClient as BankQueryClient, QueryTotalSupplyRequest},
+    bank::v1beta1::{
+        query_client::QueryClient as BankQueryClient, QueryAllBalancesRequest,
+        QueryTotalSupplyRequest,
+    },
     base::{query::v1beta1::PageRequest, v1beta1::Coin},
 };
 use cosmos_sdk_proto_althea::ibc::applications::transfer::v1::{
     query_client::QueryClient as IbcTransferQueryClient, QueryEscrowAddressRequest,
 };
+use cosmos_sdk_proto_althea::ibc::core::channel::v1::{
+    query_client::QueryClient as IbcChannelQueryClient, QueryChannelClientStateRequest,
+    QueryChannelsRequest, State as ChannelState,
+};
+use cosmos_sdk_proto_althea::ibc::lightclients::tendermint::v1::ClientState as TendermintClientState;
+use deep_space::client::PAGE;
+use log::{error, info};
 use num256::Uint256;
+use prost::Message;
 use rust_decimal::prelude::Zero;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Duration;
 use tokio::time::sleep;
+use tonic::transport::Channel;
+use web30::client::Web3;
 
 use crate::{
+    chains::{ChainConfig, ChainToken, ALTHEA_CHAIN_ID},
     config::{get_token, get_tokens, Token},
+    events::{publish_event, EventKind},
+    history::{get_current_timestamp, save_snapshot, Snapshot},
+    ibc_denoms::{get_unregistered_assets, UnregisteredAsset},
     total_suppy::get_supply_info,
+    ALTHEA_EVM_RPC, ALTHEA_NODE_GRPC, REQUEST_TIMEOUT,
 };
 
+/// How often a TVL snapshot is taken and stored for historical queries
+const TVL_SNAPSHOT_TIME: Duration = Duration::from_secs(3600);
+/// How long to wait for the supply info to be generated before taking a TVL snapshot
+const TVL_SNAPSHOT_RETRY_TIME: Duration = Duration::from_secs(60);
+/// The name of the history file TVL snapshots are stored in
+pub const TVL_HISTORY: &str = "tvl";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tvl {
     pub althea_on_chain: TokenAmount,
     pub ibc_tokens_on_chain: Vec<TokenAmount>,
     pub althea_native_erc20s_on_chain: Vec<TokenAmount>,
+    /// Tokens held in escrow by each IBC transfer channel, these have been bridged out to the counterparty
+    /// chain and are not counted in the on chain amounts above
+    #[serde(default)]
+    pub bridged_out: Vec<ChannelEscrow>,
+    /// IBC tokens on chain which are not in the token registry, only populated when requested
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unregistered_ibc_tokens_on_chain: Vec<UnregisteredAsset>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,43 +63,146 @@ pub struct TokenAmount {
     pub amount: Uint256,
 }
 
+/// The TVL of a chain other than Althea L1, which only covers its native token and the bank supply of tokens
+/// listed for it in the chains config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainTvl {
+    /// The liquid supply of the native token, from the supply info
+    pub native_on_chain: Uint256,
+    pub tokens_on_chain: Vec<ChainTokenAmount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainTokenAmount {
+    pub token: ChainToken,
+    pub amount: Uint256,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelEscrow {
+    /// The port of this channel on Althea L1
+    pub port_id: String,
+    /// The id of this channel on Althea L1
+    pub channel_id: String,
+    pub counterparty_port_id: String,
+    pub counterparty_channel_id: String,
+    /// The chain id of the chain on the other end of this channel, may be null if it could not be
+    /// determined from the light client
+    pub counterparty_chain_id: Option<String>,
+    /// The channel state, such as STATE_OPEN or STATE_CLOSED. Tokens escrowed for a channel that is no longer
+    /// open are still off chain, they remain in escrow until the channel is recovered
+    #[serde(default)]
+    pub state: String,
+    /// The address holding tokens sent out over this channel
+    pub escrow_address: String,
+    /// Registered tokens held by the escrow address
+    pub escrowed: Vec<TokenAmount>,
+}
+
 /// Type alias for the total supply result from the Cosmos bank module
 pub type TotalSupply = Vec<Coin>;
 
 pub const ALTHEA_TOKEN_DENOM: &str = "aalthea";
+/// The IBC port used by ICS-20 token transfers
+pub const IBC_TRANSFER_PORT: &str = "transfer";
+
+/// Periodically computes the TVL and stores it as a snapshot so that historical TVL can be served
+pub async fn tvl_snapshot_worker() {
+    loop {
+        // the native token TVL comes from the supply info, a snapshot without it would be invalid
+        if get_supply_info(ALTHEA_CHAIN_ID).is_none() {
+            sleep(TVL_SNAPSHOT_RETRY_TIME).await;
+            continue;
+        }
+        match get_unpriced_tvl(
+            ALTHEA_NODE_GRPC.to_string(),
+            ALTHEA_EVM_RPC.to_string(),
+            false,
+        )
+        .await
+        {
+            Ok(v) => {
+                let snapshot = Snapshot {
+                    timestamp: get_current_timestamp(),
+                    data: v,
+                };
+                publish_event(EventKind::Tvl, &snapshot);
+                match save_snapshot(TVL_HISTORY, &snapshot) {
+                    Ok(()) => info!("Successfully saved TVL snapshot!"),
+                    Err(e) => error!("Failed to save TVL snapshot with {:?}", e),
+                }
+            }
+            Err(e) => error!("Failed to compute TVL snapshot with {:?}", e),
+        }
+        sleep(TVL_SNAPSHOT_TIME).await;
+    }
+}
 
 // Fetches and computes the supply of bridged IBC tokens, native althea (from the total supply thread info), and altheaL1-native erc20s
-pub async fn get_unpriced_tvl(grpc: String) -> Result<Tvl, String> {
+// IBC tokens which are not in the token registry are only included if include_unregistered is set
+pub async fn get_unpriced_tvl(
+    grpc: String,
+    evm_rpc: String,
+    include_unregistered: bool,
+) -> Result<Tvl, String> {
     let supply = get_total_supply(&grpc).await?;
+    let unregistered_ibc_tokens_on_chain = if include_unregistered {
+        get_unregistered_assets(&supply, &grpc).await?
+    } else {
+        Vec::new()
+    };
     let supply = filter_supply_by_tokens(supply);
     let tokens_on_chain = get_tokens_on_chain(&supply, &grpc).await?;
-    let althea_supply =
-        get_supply_info().map_or_else(Uint256::zero, |info| info.total_liquid_supply);
-
+    let evm_tokens_on_chain = get_evm_tokens_on_chain(&evm_rpc).await?;
+    let althea_supply = get_supply_info(ALTHEA_CHAIN_ID)
+        .map_or_else(Uint256::zero, |info| info.total_liquid_supply);
+    let althea_token = get_token(ALTHEA_TOKEN_DENOM).unwrap();
+
+    // escrow accounts are regular accounts and are counted in the liquid supply, but the tokens
+    // they hold have been bridged out and are not on Althea L1
+    let mut althea_bridged_out = Uint256::zero();
+    for escrow in tokens_on_chain.escrows.iter() {
+        for e in escrow.escrowed.iter() {
+            if e.token == althea_token {
+                althea_bridged_out += e.amount;
+            }
+        }
+    }
     let althea_on_chain = TokenAmount {
-        token: get_token(ALTHEA_TOKEN_DENOM).unwrap(),
-        amount: althea_supply,
+        token: althea_token,
+        amount: if althea_supply > althea_bridged_out {
+            althea_supply - althea_bridged_out
+        } else {
+            Uint256::zero()
+        },
     };
 
     let mut ibc_tokens_on_chain: Vec<TokenAmount> = Vec::new();
     let mut althea_native_erc20s_on_chain: Vec<TokenAmount> = Vec::new();
-    for (t, v) in tokens_on_chain {
-        if t.althea_denom.starts_with("ibc/") {
-            ibc_tokens_on_chain.push(TokenAmount {
-                token: t,
-                amount: v,
-            });
-        } else if t.althea_denom != ALTHEA_TOKEN_DENOM {
-            althea_native_erc20s_on_chain.push(TokenAmount {
-                token: t,
-                amount: v,
-            });
+    for (t, v) in tokens_on_chain.on_chain {
+        match t.althea_denom.as_deref() {
+            Some(ALTHEA_TOKEN_DENOM) => {}
+            Some(denom) if denom.starts_with("ibc/") => {
+                ibc_tokens_on_chain.push(TokenAmount {
+                    token: t,
+                    amount: v,
+                });
+            }
+            _ => {
+                althea_native_erc20s_on_chain.push(TokenAmount {
+                    token: t,
+                    amount: v,
+                });
+            }
         }
     }
+    althea_native_erc20s_on_chain.extend(evm_tokens_on_chain);
     let tvl = Tvl {
         althea_on_chain,
         ibc_tokens_on_chain,
         althea_native_erc20s_on_chain,
+        bridged_out: tokens_on_chain.escrows,
+        unregistered_ibc_tokens_on_chain,
     };
     Ok(tvl)
 }
@@ -77,11 +214,74 @@ pub fn filter_supply_by_tokens(supply: TotalSupply) -> TotalSupply {
         .filter(|coin| {
             tokens
                 .values()
-                .any(|token| token.althea_denom == coin.denom)
+                .any(|token| token.althea_denom.as_ref() == Some(&coin.denom))
         })
         .collect()
 }
 
+pub async fn get_unpriced_chain_tvl(chain: &ChainConfig) -> Result<ChainTvl, String> {
+    let supply = get_total_supply(chain.grpc()).await?;
+    let native_on_chain = get_supply_info(&chain.chain_id)
+        .map_or_else(Uint256::zero, |info| info.total_liquid_supply);
+    let mut tokens_on_chain = Vec::new();
+    for coin in supply {
+        if coin.denom == chain.native_denom {
+            continue;
+        }
+        let token = match chain.get_token(&coin.denom) {
+            Some(t) => t,
+            None => continue,
+        };
+        let amount: Uint256 = match coin.amount.parse() {
+            Ok(amount) => amount,
+            Err(e) => {
+                log::error!(
+                    "Failed to parse supply amount '{}' for denom {} on {}: {}",
+                    coin.amount,
+                    coin.denom,
+                    chain.chain_id,
+                    e
+                );
+                continue;
+            }
+        };
+        tokens_on_chain.push(ChainTokenAmount {
+            token: token.clone(),
+            amount,
+        });
+    }
+    Ok(ChainTvl {
+        native_on_chain,
+        tokens_on_chain,
+    })
+}
+
+/// Gets the total supply of every registered token that only exists in the Althea L1 EVM (has no bank denom)
+/// by calling totalSupply() on the ERC20 contract over JSON-RPC
+pub async fn get_evm_tokens_on_chain(evm_rpc: &str) -> Result<Vec<TokenAmount>, String> {
+    let web3 = Web3::new(evm_rpc, REQUEST_TIMEOUT);
+    // totalSupply() is a view function, the caller does not need a balance
+    let caller = Address::default();
+
+    let mut result = Vec::new();
+    for token in get_tokens().into_values() {
+        if token.althea_denom.is_some() {
+            continue;
+        }
+        let amount = web3
+            .get_erc20_supply(token.althea_evm_address, caller)
+            .await
+            .map_err(|e| {
+                format!(
+                    "Failed to query ERC20 total supply for {} at {}: {e}",
+                    token.symbol, token.althea_evm_address
+                )
+            })?;
+        result.push(TokenAmount { token, amount });
+    }
+    Ok(result)
+}
+
 /// Gets the total supply of all tokens from the Cosmos bank module
 pub async fn get_total_supply(grpc: &str) -> Result<TotalSupply, String> {
     let mut next_key: Option<Vec<u8>> = None;
@@ -155,148 +355,254 @@ pub async fn get_total_supply(grpc: &str) -> Result<TotalSupply, String> {
     Ok(all_supply)
 }
 
+/// The result of splitting the supply of each registered token between what is on Althea L1 and
+/// what is held in IBC escrow, having been bridged out to another chain
+#[derive(Debug, Clone)]
+pub struct TokensOnChain {
+    pub on_chain: HashMap<Token, Uint256>,
+    pub escrows: Vec<ChannelEscrow>,
+}
+
 pub async fn get_tokens_on_chain(
     supply: &TotalSupply,
     grpc: &str,
-) -> Result<HashMap<Token, Uint256>, String> {
-    let mut result = HashMap::new();
+) -> Result<TokensOnChain, String> {
+    let escrows = get_channel_escrows(grpc).await?;
+    let total_escrowed = sum_escrows(&escrows);
 
-    // Create clients for IBC transfer and bank queries
-    let mut ibc_client = IbcTransferQueryClient::connect(grpc.to_string())
-        .await
-        .map_err(|e| format!("Failed to connect to IBC transfer gRPC endpoint: {e}"))?;
+    let mut on_chain = HashMap::new();
+    // Process each coin in the supply
+    for coin in supply {
+        // Find the matching token from our config
+        let token = match get_token(&coin.denom) {
+            Some(t) => t,
+            None => continue,
+        };
+        // Parse the total supply amount
+        let total_supply: Uint256 = match coin.amount.parse() {
+            Ok(amount) => amount,
+            Err(e) => {
+                log::error!(
+                    "Failed to parse supply amount '{}' for denom {}: {}",
+                    coin.amount,
+                    coin.denom,
+                    e
+                );
+                continue;
+            }
+        };
+        let escrowed_amount = total_escrowed
+            .get(&token)
+            .cloned()
+            .unwrap_or_else(Uint256::zero);
+
+        // Calculate on-chain balance: total supply - escrowed amount
+        let on_chain_amount = if total_supply >= escrowed_amount {
+            total_supply - escrowed_amount
+        } else {
+            log::error!(
+                "Escrowed amount ({}) exceeds total supply ({}) for denom {}",
+                escrowed_amount,
+                total_supply,
+                coin.denom
+            );
+            Uint256::zero()
+        };
 
-    let ibc_port = "transfer".to_string();
-    let channel_0_id = "channel-0".to_string();
-    let mut channel0_escrow_address: String = String::new();
-    const MAX_RETRIES: u32 = 3;
-    for _ in 0..MAX_RETRIES {
-        if let Ok(response) = ibc_client
-            .escrow_address(QueryEscrowAddressRequest {
-                port_id: ibc_port.clone(),
-                channel_id: channel_0_id.clone(),
-            })
-            .await
-        {
-            channel0_escrow_address = response.into_inner().escrow_address;
-            break;
-        }
+        on_chain.insert(token, on_chain_amount);
     }
-    if channel0_escrow_address.is_empty() {
-        return Err("Failed to get escrow address for channel-0 after retries".to_string());
+
+    Ok(TokensOnChain { on_chain, escrows })
+}
+
+/// The total of each registered token held in escrow across every channel
+fn sum_escrows(escrows: &[ChannelEscrow]) -> HashMap<Token, Uint256> {
+    let mut total_escrowed: HashMap<Token, Uint256> = HashMap::new();
+    for escrow in escrows.iter() {
+        for e in escrow.escrowed.iter() {
+            *total_escrowed
+                .entry(e.token.clone())
+                .or_insert(Uint256::zero()) += e.amount;
+        }
     }
+    total_escrowed
+}
 
+/// Finds every channel on the transfer port and the registered tokens held in escrow for it, these are
+/// tokens which have been sent out over IBC to the counterparty chain. Closed channels are included, as
+/// their escrowed tokens are still on the counterparty chain
+pub async fn get_channel_escrows(grpc: &str) -> Result<Vec<ChannelEscrow>, String> {
+    let mut channel_client = IbcChannelQueryClient::connect(grpc.to_string())
+        .await
+        .map_err(|e| format!("Failed to connect to IBC channel gRPC endpoint: {e}"))?;
+    let mut ibc_client = IbcTransferQueryClient::connect(grpc.to_string())
+        .await
+        .map_err(|e| format!("Failed to connect to IBC transfer gRPC endpoint: {e}"))?;
     let mut bank_client = BankQueryClient::connect(grpc.to_string())
         .await
         .map_err(|e| format!("Failed to connect to bank gRPC endpoint: {e}"))?;
 
-    // Process each coin in the supply
-    for coin in supply {
-        // Find the matching token from our config
-        let token = get_token(&coin.denom);
-
-        if let Some(token) = token {
-            // Parse the total supply amount
-            let total_supply: Uint256 = match coin.amount.parse() {
-                Ok(amount) => amount,
-                Err(e) => {
-                    log::error!(
-                        "Failed to parse supply amount '{}' for denom {}: {}",
-                        coin.amount,
-                        coin.denom,
-                        e
-                    );
-                    continue;
-                }
-            };
-
-            // Get the escrow address for this token
-            // Standard IBC transfer port is "transfer", channel needs to come from token config
-            let port_id = "transfer".to_string();
-            let channel_id = if let Some(ref channel) = token.ibc_channel {
-                channel.clone()
-            } else {
-                // If no channel configured, assume all tokens are on-chain (no escrow)
-                result.insert(token.clone(), total_supply);
-                continue;
-            };
-
-            let escrow_address: String = if port_id == ibc_port && channel_id == channel_0_id {
-                channel0_escrow_address.clone()
-            } else {
-                let escrow_request = QueryEscrowAddressRequest {
-                    port_id: port_id.clone(),
-                    channel_id: channel_id.clone(),
-                };
-
-                match ibc_client.escrow_address(escrow_request).await {
-                    Ok(response) => response.into_inner().escrow_address,
-                    Err(e) => {
-                        log::warn!(
-                            "Failed to get escrow address for {}/{}: {}, assuming no escrow",
-                            port_id,
-                            channel_id,
-                            e
-                        );
-                        // If we can't get escrow address, assume all tokens are on-chain
-                        result.insert(token.clone(), total_supply);
-                        continue;
-                    }
-                }
-            };
+    let channels = channel_client
+        .channels(QueryChannelsRequest { pagination: PAGE })
+        .await
+        .map_err(|e| format!("Failed to query IBC channels: {e}"))?
+        .into_inner()
+        .channels;
+
+    let mut result = Vec::new();
+    for channel in channels {
+        if channel.port_id != IBC_TRANSFER_PORT {
+            continue;
+        }
+        let escrow_address = ibc_client
+            .escrow_address(QueryEscrowAddressRequest {
+                port_id: channel.port_id.clone(),
+                channel_id: channel.channel_id.clone(),
+            })
+            .await
+            .map_err(|e| {
+                format!(
+                    "Failed to get escrow address for {}/{}: {e}",
+                    channel.port_id, channel.channel_id
+                )
+            })?
+            .into_inner()
+            .escrow_address;
+
+        let balances = bank_client
+            .all_balances(QueryAllBalancesRequest {
+                address: escrow_address.clone(),
+                pagination: PAGE,
+            })
+            .await
+            .map_err(|e| {
+                format!("Failed to query balances for escrow address {escrow_address}: {e}")
+            })?
+            .into_inner()
+            .balances;
+        let mut escrowed = Vec::new();
+        for coin in balances {
+            if let Some(token) = get_token(&coin.denom) {
+                let amount: Uint256 = coin.amount.parse().map_err(|e| {
+                    format!(
+                        "Failed to parse escrowed amount '{}' for denom {}: {e}",
+                        coin.amount, coin.denom
+                    )
+                })?;
+                escrowed.push(TokenAmount { token, amount });
+            }
+        }
 
-            // Query the balance at the escrow address for this denom
-            let balance_request =
-                cosmos_sdk_proto_althea::cosmos::bank::v1beta1::QueryBalanceRequest {
-                    address: escrow_address.clone(),
-                    denom: coin.denom.clone(),
-                };
+        let counterparty_chain_id =
+            get_counterparty_chain_id(&mut channel_client, &channel.port_id, &channel.channel_id)
+                .await;
+        let counterparty = channel.counterparty.unwrap_or_default();
+        let state =
+            ChannelState::try_from(channel.state).unwrap_or(ChannelState::UninitializedUnspecified);
+        result.push(ChannelEscrow {
+            state: state.as_str_name().to_string(),
+            port_id: channel.port_id,
+            channel_id: channel.channel_id,
+            counterparty_port_id: counterparty.port_id,
+            counterparty_channel_id: counterparty.channel_id,
+            counterparty_chain_id,
+            escrow_address,
+            escrowed,
+        });
+    }
 
-            let escrowed_amount: Uint256 = match bank_client.balance(balance_request).await {
-                Ok(response) => {
-                    if let Some(balance) = response.into_inner().balance {
-                        match balance.amount.parse() {
-                            Ok(amount) => amount,
-                            Err(e) => {
-                                log::error!(
-                                    "Failed to parse escrowed amount '{}' for denom {}: {}",
-                                    balance.amount,
-                                    coin.denom,
-                                    e
-                                );
-                                Uint256::zero()
-                            }
-                        }
-                    } else {
-                        Uint256::zero()
-                    }
-                }
-                Err(e) => {
-                    log::warn!(
-                        "Failed to query balance for escrow address {}: {}, assuming zero escrow",
-                        escrow_address,
-                        e
-                    );
-                    Uint256::zero()
-                }
-            };
+    Ok(result)
+}
 
-            // Calculate on-chain balance: total supply - escrowed amount
-            let on_chain_amount = if total_supply >= escrowed_amount {
-                total_supply - escrowed_amount
-            } else {
-                log::error!(
-                    "Escrowed amount ({}) exceeds total supply ({}) for denom {}",
-                    escrowed_amount,
-                    total_supply,
-                    coin.denom
-                );
-                Uint256::zero()
-            };
+/// Gets the chain id of the counterparty chain from the light client backing the channel, returns None
+/// if the client state can't be retrieved or is not a tendermint light client
+async fn get_counterparty_chain_id(
+    channel_client: &mut IbcChannelQueryClient<Channel>,
+    port_id: &str,
+    channel_id: &str,
+) -> Option<String> {
+    const TENDERMINT_CLIENT_STATE_TYPE_URL: &str = "/ibc.lightclients.tendermint.v1.ClientState";
+
+    let response = channel_client
+        .channel_client_state(QueryChannelClientStateRequest {
+            port_id: port_id.to_string(),
+            channel_id: channel_id.to_string(),
+        })
+        .await;
+    let client_state = match response {
+        Ok(v) => v.into_inner().identified_client_state?.client_state?,
+        Err(e) => {
+            log::warn!(
+                "Failed to get client state for {}/{}: {}",
+                port_id,
+                channel_id,
+                e
+            );
+            return None;
+        }
+    };
+    if client_state.type_url != TENDERMINT_CLIENT_STATE_TYPE_URL {
+        return None;
+    }
+    TendermintClientState::decode(client_state.value.as_slice())
+        .ok()
+        .map(|c| c.chain_id)
+}
 
-            result.insert(token.clone(), on_chain_amount);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::get_tokens;
+
+    fn escrow(channel_id: &str, state: ChannelState, escrowed: Vec<TokenAmount>) -> ChannelEscrow {
+        ChannelEscrow {
+            port_id: IBC_TRANSFER_PORT.to_string(),
+            channel_id: channel_id.to_string(),
+            counterparty_port_id: IBC_TRANSFER_PORT.to_string(),
+            counterparty_channel_id: "channel-0".to_string(),
+            counterparty_chain_id: None,
+            state: state.as_str_name().to_string(),
+            escrow_address: String::new(),
+            escrowed,
         }
     }
 
-    Ok(result)
+    #[test]
+    fn test_sum_escrows() {
+        let tokens = get_tokens();
+        let usdc = tokens["USDC"].clone();
+        let usdt = tokens["USDT"].clone();
+        let escrows = vec![
+            escrow(
+                "channel-1",
+                ChannelState::Open,
+                vec![
+                    TokenAmount {
+                        token: usdc.clone(),
+                        amount: 100u8.into(),
+                    },
+                    TokenAmount {
+                        token: usdt.clone(),
+                        amount: 5u8.into(),
+                    },
+                ],
+            ),
+            // a closed channel's escrow is still bridged out
+            escrow(
+                "channel-2",
+                ChannelState::Closed,
+                vec![TokenAmount {
+                    token: usdc.clone(),
+                    amount: 50u8.into(),
+                }],
+            ),
+            escrow("channel-3", ChannelState::Open, vec![]),
+        ];
+
+        let totals = sum_escrows(&escrows);
+        assert_eq!(totals.len(), 2);
+        assert_eq!(totals[&usdc], 150u8.into());
+        assert_eq!(totals[&usdt], 5u8.into());
+        assert!(sum_escrows(&[]).is_empty());
+    }
 }
@@ -0,0 +1,309 @@
+//! Scans Althea L1 blocks for transactions, maintaining statistics that can't be computed from current chain
+//! state. The index is persisted after every batch of blocks so a restart resumes where it left off
+
+use crate::{
+    accounts::ActiveAccounts,
+    history::{day_start, load_state, save_state},
+    microtx::{merge_microtx_volume, record_microtxs, MicrotxVolume, MSG_MICROTX_TYPE_URL},
+    ALTHEA_NODE_GRPC, ALTHEA_PREFIX,
+};
+use althea_proto::althea::microtx::v1::MsgMicrotx;
+use clarity::Address as EthAddress;
+use cosmos_sdk_proto_althea::cosmos::base::tendermint::v1beta1::service_client::ServiceClient as TendermintServiceClient;
+use cosmos_sdk_proto_althea::cosmos::base::tendermint::v1beta1::{
+    GetBlockByHeightRequest, GetLatestBlockRequest,
+};
+use cosmos_sdk_proto_althea::cosmos::tx::v1beta1::service_client::ServiceClient as TxServiceClient;
+use cosmos_sdk_proto_althea::cosmos::tx::v1beta1::GetTxsEventRequest;
+use cosmos_sdk_proto_althea::tendermint::abci::Event;
+use cosmos_sdk_proto_althea::tendermint::types::Block;
+use deep_space::error::CosmosGrpcError;
+use deep_space::Address;
+use log::{error, info};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::sleep;
+use tonic::transport::Channel;
+
+/// How long to wait before checking for new blocks once the index has caught up
+const LOOP_TIME: Duration = Duration::from_secs(30);
+/// The most blocks indexed before the index is saved
+const BLOCKS_PER_BATCH: u64 = 500;
+/// The name of the state file the index is stored in
+const TX_INDEX_STATE: &str = "tx_index";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TxIndex {
+    /// The last block included in the index, zero if nothing has been indexed
+    pub last_indexed_height: u64,
+    #[serde(default)]
+    pub microtx_volume: MicrotxVolume,
+    #[serde(default)]
+    pub active_accounts: ActiveAccounts,
+}
+
+lazy_static! {
+    static ref TX_INDEX: Arc<RwLock<Option<TxIndex>>> = Arc::new(RwLock::new(None));
+}
+
+fn set_tx_index(input: TxIndex) {
+    let mut r = TX_INDEX.write().unwrap();
+    *r = Some(input);
+}
+
+pub fn get_tx_index() -> Option<TxIndex> {
+    TX_INDEX.read().unwrap().clone()
+}
+
+pub async fn tx_indexer_worker() {
+    // after a restart the index in memory is newer than the saved one
+    if get_tx_index().is_none() {
+        if let Some(index) = load_state::<TxIndex>(TX_INDEX_STATE) {
+            info!("Resuming tx index from block {}", index.last_indexed_height);
+            set_tx_index(index);
+        }
+    }
+
+    loop {
+        let caught_up = {
+            let mut index = get_tx_index().unwrap_or_default();
+            let start = index.last_indexed_height;
+            let res = index_blocks(ALTHEA_NODE_GRPC, &mut index, BLOCKS_PER_BATCH).await;
+            // blocks indexed before an error are still saved
+            if index.last_indexed_height != start {
+                info!("Indexed blocks up to {}", index.last_indexed_height);
+                if let Err(e) = save_state(TX_INDEX_STATE, &index) {
+                    error!("Failed to save tx index with {:?}", e);
+                }
+                set_tx_index(index);
+            }
+            match res {
+                Ok(caught_up) => caught_up,
+                Err(e) => {
+                    error!("Failed to index blocks with {:?}", e);
+                    true
+                }
+            }
+        };
+        if caught_up {
+            sleep(LOOP_TIME).await;
+        }
+    }
+}
+
+/// Indexes up to `max_blocks` blocks after the last indexed block, returns true if the index has reached the
+/// latest block
+pub async fn index_blocks(
+    grpc: &str,
+    index: &mut TxIndex,
+    max_blocks: u64,
+) -> Result<bool, CosmosGrpcError> {
+    let mut tmrpc = TendermintServiceClient::connect(grpc.to_string()).await?;
+    let mut txrpc = TxServiceClient::connect(grpc.to_string()).await?;
+
+    let latest = tmrpc
+        .get_latest_block(GetLatestBlockRequest {})
+        .await?
+        .into_inner()
+        .block
+        .and_then(|b| b.header)
+        .map(|h| h.height as u64)
+        .ok_or_else(|| CosmosGrpcError::BadResponse("No latest block".to_string()))?;
+    if index.last_indexed_height == 0 {
+        index.last_indexed_height = get_earliest_height(&mut tmrpc).await? - 1;
+    }
+
+    let end = latest.min(index.last_indexed_height + max_blocks);
+    for height in index.last_indexed_height + 1..=end {
+        let block = tmrpc
+            .get_block_by_height(GetBlockByHeightRequest {
+                height: height as i64,
+            })
+            .await?
+            .into_inner()
+            .block
+            .ok_or_else(|| CosmosGrpcError::BadResponse(format!("No block at {height}")))?;
+        index_block(&mut txrpc, index, block).await?;
+        index.last_indexed_height = height;
+    }
+    Ok(end == latest)
+}
+
+/// Adds every transaction in a block to the index, the index is only modified once every query for the
+/// block has succeeded so a failed block can be retried without double counting
+async fn index_block(
+    txrpc: &mut TxServiceClient<Channel>,
+    index: &mut TxIndex,
+    block: Block,
+) -> Result<(), CosmosGrpcError> {
+    let header = block
+        .header
+        .ok_or_else(|| CosmosGrpcError::BadResponse("Block without header".to_string()))?;
+    let timestamp = header
+        .time
+        .map(|t| t.seconds as u64)
+        .ok_or_else(|| CosmosGrpcError::BadResponse("Block without time".to_string()))?;
+    let tx_count = block.data.map(|d| d.txs.len()).unwrap_or_default() as u64;
+    if tx_count == 0 {
+        return Ok(());
+    }
+
+    // the block only contains the transactions themselves, results and events come from the tx index
+    let response = txrpc
+        .get_txs_event(GetTxsEventRequest {
+            events: vec![format!("tx.height={}", header.height)],
+            page: 1,
+            limit: tx_count,
+            ..Default::default()
+        })
+        .await?
+        .into_inner();
+    if response.txs.len() as u64 != tx_count || response.tx_responses.len() as u64 != tx_count {
+        return Err(CosmosGrpcError::BadResponse(format!(
+            "Expected {tx_count} txs at height {} got {}",
+            header.height,
+            response.tx_responses.len()
+        )));
+    }
+
+    let mut microtxs = Vec::new();
+    let mut signers = Vec::new();
+    for (tx, result) in response.txs.into_iter().zip(response.tx_responses) {
+        // failed transactions still pay fees, so their signers count as active
+        signers.extend(get_signers(&result.events));
+        if result.code != 0 {
+            continue;
+        }
+        let msgs: Vec<MsgMicrotx> = tx
+            .body
+            .map(|b| b.messages)
+            .unwrap_or_default()
+            .iter()
+            .filter(|m| m.type_url == MSG_MICROTX_TYPE_URL)
+            .filter_map(|m| MsgMicrotx::decode(m.value.as_slice()).ok())
+            .collect();
+        if !msgs.is_empty() {
+            microtxs.push((msgs, result.events));
+        }
+    }
+
+    // a malformed microtx fails the whole block so that it is retried without anything counted twice
+    let mut block_volume = MicrotxVolume::new();
+    for (msgs, events) in microtxs {
+        record_microtxs(&mut block_volume, timestamp, &msgs, &events).map_err(|e| {
+            CosmosGrpcError::BadResponse(format!("{e} at height {}", header.height))
+        })?;
+    }
+    merge_microtx_volume(&mut index.microtx_volume, block_volume);
+    for signer in signers {
+        index.active_accounts.record(day_start(timestamp), signer);
+    }
+    Ok(())
+}
+
+/// Gets the addresses which signed a transaction from its events. Cosmos transactions emit a tx event with
+/// address/sequence for each signer, EVM transactions don't but the evm module emits a message event with the
+/// sender's hex address, which is converted to bech32 so each account is only counted once
+fn get_signers(events: &[Event]) -> Vec<String> {
+    let mut signers = Vec::new();
+    for event in events {
+        for attribute in event.attributes.iter() {
+            let value = String::from_utf8_lossy(&attribute.value);
+            if event.r#type == "tx" && attribute.key == b"acc_seq" {
+                if let Some((address, _)) = value.rsplit_once('/') {
+                    signers.push(address.to_string());
+                }
+            } else if event.r#type == "message"
+                && attribute.key == b"sender"
+                && value.starts_with("0x")
+            {
+                let address = value
+                    .parse::<EthAddress>()
+                    .ok()
+                    .and_then(|a| Address::from_slice(a.as_bytes(), ALTHEA_PREFIX).ok())
+                    .and_then(|a| a.to_bech32(ALTHEA_PREFIX).ok());
+                if let Some(address) = address {
+                    signers.push(address);
+                }
+            }
+        }
+    }
+    signers
+}
+
+/// Finds the first block this node has, pruned nodes report it in the error for unavailable heights
+pub async fn get_earliest_height(
+    tmrpc: &mut TendermintServiceClient<Channel>,
+) -> Result<u64, CosmosGrpcError> {
+    match tmrpc
+        .get_block_by_height(GetBlockByHeightRequest { height: 1 })
+        .await
+    {
+        Ok(_) => Ok(1),
+        Err(e) => match parse_lowest_height(e.message()) {
+            Some(height) => Ok(height),
+            None => Err(e.into()),
+        },
+    }
+}
+
+/// Parses the lowest available height out of an error like "height 1 is not available, lowest height is 1500"
+fn parse_lowest_height(message: &str) -> Option<u64> {
+    let (_, rest) = message.split_once("lowest height is ")?;
+    rest.split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmos_sdk_proto_althea::tendermint::abci::EventAttribute;
+
+    #[test]
+    fn test_parse_lowest_height() {
+        assert_eq!(
+            parse_lowest_height("height 1 is not available, lowest height is 1500"),
+            Some(1500)
+        );
+        assert_eq!(
+            parse_lowest_height(
+                "rpc error: height 1 is not available, lowest height is 42: invalid request"
+            ),
+            Some(42)
+        );
+        assert_eq!(parse_lowest_height("connection refused"), None);
+    }
+
+    #[test]
+    fn test_get_signers() {
+        let event = |kind: &str, key: &str, value: &str| Event {
+            r#type: kind.to_string(),
+            attributes: vec![EventAttribute {
+                key: key.as_bytes().to_vec(),
+                value: value.as_bytes().to_vec(),
+                index: false,
+            }],
+        };
+        let zero = Address::from_slice(&[0; 20], ALTHEA_PREFIX)
+            .unwrap()
+            .to_bech32(ALTHEA_PREFIX)
+            .unwrap();
+
+        let signers = get_signers(&[
+            event("tx", "acc_seq", &format!("{zero}/12")),
+            event(
+                "message",
+                "sender",
+                "0x0000000000000000000000000000000000000000",
+            ),
+            // bech32 message senders may be module accounts and are ignored
+            event("message", "sender", &zero),
+            event("tx", "fee", "10aalthea"),
+        ]);
+        assert_eq!(signers, vec![zero.clone(), zero]);
+    }
+}